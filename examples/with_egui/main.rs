@@ -10,8 +10,9 @@ use music_notation::note::articulation::Velocity;
 use music_notation::note::harmony::{Chroma, Interval, Pitch};
 use music_notation::note::rhythm::{Time, TimeSignature};
 use music_notation::note::Note;
-use music_notation::score::edit::{Cursor, EditState};
-use music_notation::score::rendering::{MidiRoll, MidiRollViewport, Rect, Vec2};
+use music_notation::score::edit::{Cursor, Edit, EditState};
+use music_notation::score::index::NoteIndex;
+use music_notation::score::rendering::{MidiRoll, Rect, Vec2};
 use music_notation::score::Score;
 use player::{start_player, Player};
 
@@ -52,6 +53,8 @@ fn boomwhacker_color(chroma: Chroma) -> Color32 {
 pub struct ScoreEditor {
     pub score: Score,
     pub edit: EditState,
+    /// One per part for finding the visible notes, updated with every edit.
+    pub indices: Vec<NoteIndex>,
     pub view: MidiRoll,
     pub play_line: Time,
     pub playing: bool, // TODO: Move to player
@@ -60,6 +63,7 @@ pub struct ScoreEditor {
 impl ScoreEditor {
     fn new(score: Score) -> Self {
         ScoreEditor {
+            indices: score.parts.iter().map(NoteIndex::from).collect(),
             score,
             ..Default::default()
         }
@@ -96,39 +100,17 @@ impl ScoreEditor {
         for event in player.events.try_iter() {
             match event {
                 player::PlayerEvents::Time(time) => self.play_line = time,
+                player::PlayerEvents::Finished(_) => (),
             }
         }
 
         if ui.input(|i| i.key_pressed(egui::Key::Space)) {
             if self.playing {
-                player.commands.send(player::PlayerCommands::Pause).unwrap();
+                player.pause();
                 self.playing = false;
             }
             else {
-                player
-                    .commands
-                    .send(player::PlayerCommands::SetBuffer({
-                        let mut notes: Vec<_> = self
-                            .score
-                            .parts
-                            .iter()
-                            .enumerate()
-                            .filter_map(|(i, part)| {
-                                self.selected_parts.contains(&i).then_some(part)
-                            })
-                            .flat_map(|t| t.notes.iter())
-                            .cloned()
-                            .collect();
-                        notes.sort_unstable_by_key(|n| n.time);
-                        notes
-                    }))
-                    .unwrap();
-                player
-                    .commands
-                    .send(player::PlayerCommands::SetTime(self.play_line))
-                    .unwrap();
-                player.commands.send(player::PlayerCommands::Start).unwrap();
-
+                player.play(&self.score, &self.selected_parts, self.play_line);
                 self.playing = true;
             }
         }
@@ -177,28 +159,7 @@ impl ScoreEditor {
             if let Some(pointer_pos) = pointer_pos {
                 self.play_line = pointer_pos.0;
                 if self.playing {
-                    player
-                        .commands
-                        .send(player::PlayerCommands::SetTime(self.play_line))
-                        .unwrap();
-                    player
-                        .commands
-                        .send(player::PlayerCommands::SetBuffer({
-                            let mut notes: Vec<_> = self
-                                .score
-                                .parts
-                                .iter()
-                                .enumerate()
-                                .filter_map(|(i, part)| {
-                                    self.selected_parts.contains(&i).then_some(part)
-                                })
-                                .flat_map(|t| t.notes.iter())
-                                .cloned()
-                                .collect();
-                            notes.sort_unstable_by_key(|n| n.time);
-                            notes
-                        }))
-                        .unwrap();
+                    player.seek(self.play_line);
                 }
             }
         }
@@ -221,13 +182,17 @@ impl ScoreEditor {
 
         let mut any_note_hovered = false;
 
-        for (i, part) in self.score.parts.iter().enumerate() {
+        for i in 0..self.score.parts.len() {
             let part_selected = self.selected_parts.contains(&i);
             if !part_selected {
                 continue;
             }
 
-            for note in Self::visible_note_range_in(&self.view.viewport, part) {
+            let viewport = &self.view.viewport;
+            // Notes reach a halfstep above their pitch
+            let pitches =
+                (viewport.pitch_range.start..viewport.pitch_range.end + Interval::HALFSTEP).into();
+            for note in self.indices[i].notes_in_area(viewport.time_range, pitches) {
                 let rect = self.paint_note(note, ui, &painter, part_selected);
                 let note_hovered = pointer_pos_raw.map(|p| rect.contains(p)).unwrap_or(false);
                 any_note_hovered |= note_hovered;
@@ -270,11 +235,12 @@ impl ScoreEditor {
                         ..Default::default()
                     };
 
-                    let notes = &mut self.score.parts[0].notes;
-                    let insert_at = notes
-                        .binary_search_by_key(&position, |note| note.time)
-                        .unwrap_or_else(|i| i);
-                    notes.insert(insert_at, new_note);
+                    if let Some(edit) = Edit::insert_note(&self.score, 0, new_note) {
+                        for (part, index) in self.indices.iter_mut().enumerate() {
+                            index.apply(part, &edit);
+                        }
+                        self.edit.apply(&mut self.score, edit);
+                    }
                 }
             }
         }
@@ -297,28 +263,6 @@ impl ScoreEditor {
         }
     }
 
-    fn visible_note_range_in<'a>(
-        viewport: &MidiRollViewport,
-        part: &'a music_notation::score::Part,
-    ) -> impl Iterator<Item = &'a Note> + 'a {
-        let start = match part
-            .notes
-            .binary_search_by_key(&viewport.time_range.start, |note| note.time + note.duration)
-        {
-            Ok(i) => i,
-            Err(i) => i,
-        };
-        let end = match part
-            .notes
-            .binary_search_by_key(&viewport.time_range.end, |note| note.time)
-        {
-            Ok(i) => i,
-            Err(i) => i,
-        };
-
-        part.notes[start..end].iter()
-    }
-
     fn paint_beat_lines(&self, painter: &egui::Painter) {
         let time_sig = TimeSignature::default();
         for (i, time) in time_sig
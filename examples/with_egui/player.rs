@@ -1,46 +1,125 @@
-use std::collections::VecDeque;
+use std::collections::HashSet;
+use std::f64::consts::TAU;
+use std::sync::mpsc::{self, Receiver, SyncSender};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use music_notation::note::rhythm::{Duration, Time};
+use music_notation::audio::{
+    BasicSynth,
+    Patch,
+    PlaybackEvent,
+    PlaybackEventKind,
+    Scheduler,
+    SynthNote,
+    Waveform,
+};
+use music_notation::instrument::drums::GM_PERCUSSION_CHANNEL;
+use music_notation::note::harmony::{Interval, Pitch};
+use music_notation::note::rhythm::Time;
 use music_notation::note::Note;
+use music_notation::score::Score;
+
+/// Most notes sounding at once, including released ones that are still fading out.
+const MAX_VOICES: usize = 256;
+
+/// Everything the audio thread needs to play a score, prepared on the UI thread.
+pub struct Playback {
+    pub scheduler:  Scheduler,
+    /// Patch of every part.
+    pub patches:    Vec<Patch>,
+    /// For notes sent to the percussion channel in melodic parts.
+    pub percussion: Patch,
+}
 
 pub enum PlayerCommands {
-    SetBuffer(Vec<Note>),
-    SetTime(Time),
-    Start,
+    Play(Box<Playback>),
+    Seek(Time),
     Pause,
 }
 
 pub enum PlayerEvents {
     Time(Time),
+    /// A replaced playback, handed back so it's freed outside the audio thread.
+    Finished(Box<Playback>),
 }
 
+/// An oscillator playing one note.
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    part: usize,
+    channel: u8,
+    pitch: Pitch,
+    velocity: f32,
+    /// In halfsteps.
+    bend: f32,
+    patch: Patch,
+    phase: f64,
+    /// Seconds since the note started.
+    age: f64,
+    /// Age at which the note was released.
+    released: Option<f64>,
+}
+impl Voice {
+    fn plays(&self, event: &PlaybackEvent) -> bool {
+        self.released.is_none()
+            && self.part == event.part
+            && self.channel == event.channel
+            && self.pitch == event.kind.pitch()
+    }
+    fn release(&mut self) { self.released.get_or_insert(self.age); }
+    fn finished(&self) -> bool {
+        self.released
+            .is_some_and(|released| self.age >= released + self.patch.envelope.release)
+    }
+
+    fn next_sample(&mut self, noise: &mut u32, seconds_per_frame: f64) -> f32 {
+        let length = self.released.unwrap_or(f64::INFINITY);
+        let level = self.patch.envelope.level(self.age, length);
+        let value = match self.patch.waveform {
+            Waveform::Sine => (self.phase * TAU).sin() as f32,
+            Waveform::Triangle => (1.0 - 4.0 * (self.phase - 0.5).abs()) as f32,
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                }
+                else {
+                    -1.0
+                }
+            }
+            Waveform::Sawtooth => (2.0 * self.phase - 1.0) as f32,
+            Waveform::Noise => {
+                // xorshift32
+                *noise ^= *noise << 13;
+                *noise ^= *noise >> 17;
+                *noise ^= *noise << 5;
+                *noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+            }
+        };
+        let frequency = (self.pitch + Interval(self.bend)).frequency_hertz() as f64;
+        self.phase = (self.phase + frequency * seconds_per_frame).fract();
+        self.age += seconds_per_frame;
+        value * level * self.velocity * self.patch.gain
+    }
+}
+
+/// Lives in the audio callback. Everything it needs is allocated up front or on the UI thread.
 pub struct PlayerState {
-    pub upcoming_notes: VecDeque<Note>,
-    pub active_notes: VecDeque<Note>,
-    pub commands: std::sync::mpsc::Receiver<PlayerCommands>,
-    pub events: std::sync::mpsc::Sender<PlayerEvents>,
-    pub current_time_in_beats: f64,
-    pub beats_to_seconds: f64,
-    pub playing: bool,
+    pub playback: Option<Box<Playback>>,
+    voices: Vec<Voice>,
+    noise: u32,
+    pub commands: Receiver<PlayerCommands>,
+    pub events: SyncSender<PlayerEvents>,
 }
 impl PlayerState {
-    pub fn new() -> (
-        Self,
-        std::sync::mpsc::Sender<PlayerCommands>,
-        std::sync::mpsc::Receiver<PlayerEvents>,
-    ) {
-        let (command_send, command_receive) = std::sync::mpsc::channel();
-        let (event_send, event_receive) = std::sync::mpsc::channel();
+    pub fn new() -> (Self, SyncSender<PlayerCommands>, Receiver<PlayerEvents>) {
+        let (command_send, command_receive) = mpsc::sync_channel(16);
+        let (event_send, event_receive) = mpsc::sync_channel(64);
         (
             Self {
-                upcoming_notes: VecDeque::new(),
-                active_notes: VecDeque::new(),
+                playback: None,
+                voices:   Vec::with_capacity(MAX_VOICES),
+                noise:    0x2545_f491,
                 commands: command_receive,
-                events: event_send,
-                current_time_in_beats: 0.0,
-                beats_to_seconds: 60.0 / 120.0,
-                playing: false,
+                events:   event_send,
             },
             command_send,
             event_receive,
@@ -48,116 +127,165 @@ impl PlayerState {
     }
 
     pub fn update(&mut self, buffer: &mut [f32], sample_rate: u32, channels: usize) {
-        let sample_rate = sample_rate as f64;
-        let num_samples = buffer.len() / channels;
-
         self.handle_commands();
 
-        if !self.playing {
-            return;
-        }
-
-        let buffer_end_time =
-            self.current_time_in_beats + num_samples as f64 / sample_rate / self.beats_to_seconds;
-
-        // Add notes that have started to the active notes
-        {
-            let buffer_len_beats = num_samples as f64 / sample_rate * self.beats_to_seconds;
-            let buffer_end_musical_time = Time::ZERO
-                + Duration::from_beats_f64(self.current_time_in_beats + buffer_len_beats);
+        let frames = buffer.len() / channels;
+        let mut rendered = 0;
+        if let Some(playback) = self.playback.as_deref_mut() {
+            let Playback {
+                scheduler,
+                patches,
+                percussion,
+            } = playback;
+            let (voices, noise) = (&mut self.voices, &mut self.noise);
+            scheduler.process(frames as u32, |event| {
+                // Render up to the event so it starts on its own frame
+                let frame = (event.frame as usize).max(rendered);
+                let out = &mut buffer[rendered * channels..frame * channels];
+                render(voices, noise, out, sample_rate, channels);
+                rendered = frame;
 
-            while !self.upcoming_notes.is_empty()
-                && self.upcoming_notes[0].time <= buffer_end_musical_time
-            {
-                self.active_notes
-                    .push_back(self.upcoming_notes.pop_front().unwrap());
+                let patch = match patches.get(event.part) {
+                    _ if event.channel == GM_PERCUSSION_CHANNEL => *percussion,
+                    Some(patch) => *patch,
+                    None => return,
+                };
+                start_or_stop(voices, patch, &event);
+            });
+            if scheduler.is_playing() {
+                let _ = self
+                    .events
+                    .try_send(PlayerEvents::Time(scheduler.position()));
             }
         }
-
-        // Remove notes that have ended from the active notes
-        self.active_notes.retain(|note| {
-            let note_start_beats = (note.time - Time::ZERO).beats() - self.current_time_in_beats;
-            let note_end_beats =
-                (note.time + note.duration - Time::ZERO).beats() - self.current_time_in_beats;
-            if note_end_beats < 0.0 {
-                return false;
-            }
-
-            generate_audio(
-                buffer,
-                channels,
-                sample_rate,
-                (note_start_beats * self.beats_to_seconds) * sample_rate,
-                (note_end_beats * self.beats_to_seconds) * sample_rate,
-                note.pitch.frequency_hertz() as f64,
-                note.velocity.to_f64(),
-            );
-
-            true
-        });
-
-        self.current_time_in_beats = buffer_end_time;
-        self.events
-            .send(PlayerEvents::Time(
-                Time::ZERO + Duration::from_beats_f64(self.current_time_in_beats),
-            ))
-            .unwrap();
+        // Released notes fade out even when paused
+        let out = &mut buffer[rendered * channels..frames * channels];
+        render(
+            &mut self.voices,
+            &mut self.noise,
+            out,
+            sample_rate,
+            channels,
+        );
     }
 
     fn handle_commands(&mut self) {
         while let Ok(command) = self.commands.try_recv() {
             match command {
-                PlayerCommands::SetBuffer(notes) => {
-                    self.active_notes.clear();
-                    self.upcoming_notes = notes.into_iter().collect()
+                PlayerCommands::Play(playback) => {
+                    self.voices.iter_mut().for_each(Voice::release);
+                    if let Some(old) = self.playback.replace(playback) {
+                        let _ = self.events.try_send(PlayerEvents::Finished(old));
+                    }
+                }
+                PlayerCommands::Seek(time) => {
+                    if let Some(playback) = &mut self.playback {
+                        playback.scheduler.seek(time);
+                    }
                 }
-                PlayerCommands::SetTime(time) => {
-                    self.current_time_in_beats = (time - Time::ZERO).beats();
+                PlayerCommands::Pause => {
+                    if let Some(playback) = &mut self.playback {
+                        playback.scheduler.pause();
+                    }
                 }
-                PlayerCommands::Start => self.playing = true,
-                PlayerCommands::Pause => self.playing = false,
             }
         }
     }
 }
 
-fn generate_audio(
-    buffer: &mut [f32],
-    channels: usize,
-    sample_rate: f64,
-    start_samples: f64,
-    end_samples: f64,
-    freq_hz: f64,
-    velocity: f64,
-) {
-    let buffer_len = (buffer.len() / channels) as i64;
-
-    let buf_start = (start_samples.ceil() as i64).clamp(0, buffer_len) as usize;
-    let buf_end = (end_samples.ceil() as i64).clamp(0, buffer_len) as usize;
-    if buf_end <= buf_start {
-        return;
+fn start_or_stop(voices: &mut Vec<Voice>, patch: Patch, event: &PlaybackEvent) {
+    match event.kind {
+        PlaybackEventKind::NoteOn { pitch, velocity } => {
+            let voice = Voice {
+                part: event.part,
+                channel: event.channel,
+                pitch,
+                velocity: velocity.to_f32(),
+                bend: 0.0,
+                patch,
+                phase: 0.0,
+                age: 0.0,
+                released: None,
+            };
+            // Never grow past the capacity, steal the oldest note instead
+            if voices.len() < voices.capacity() {
+                voices.push(voice);
+            }
+            else if let Some(oldest) = voices.iter_mut().max_by(|a, b| a.age.total_cmp(&b.age)) {
+                *oldest = voice;
+            }
+        }
+        PlaybackEventKind::NoteOff { .. } => {
+            for voice in voices.iter_mut().filter(|voice| voice.plays(event)) {
+                voice.release();
+            }
+        }
+        PlaybackEventKind::Bend { halfsteps, .. } => {
+            for voice in voices.iter_mut().filter(|voice| voice.plays(event)) {
+                voice.bend = halfsteps;
+            }
+        }
     }
+}
 
-    let attack_duration = sample_rate * (0.02 * velocity + 0.06 * (1.0 - velocity));
-    let decay_duration = sample_rate * 0.02;
-
-    let step = 2.0 * std::f64::consts::PI * freq_hz / sample_rate;
-    let mut w = (buf_start as f64 - start_samples) * step;
-    for i in buf_start..buf_end {
-        let decay = ((end_samples - i as f64) / decay_duration).clamp(0.0, 1.0);
-        let attack = ((i as f64 - start_samples) / attack_duration).clamp(0.0, 1.0);
-        let val = (velocity * attack * decay * w.sin()) as f32;
-        for sample in buffer[i * channels..(i + 1) * channels].iter_mut() {
-            *sample += val;
+/// Adds the voices to `out` and drops the ones that have faded out.
+fn render(
+    voices: &mut Vec<Voice>,
+    noise: &mut u32,
+    out: &mut [f32],
+    sample_rate: u32,
+    channels: usize,
+) {
+    let seconds_per_frame = 1.0 / sample_rate as f64;
+    for frame in out.chunks_exact_mut(channels) {
+        let value = voices
+            .iter_mut()
+            .map(|voice| voice.next_sample(noise, seconds_per_frame))
+            .sum::<f32>();
+        for sample in frame {
+            *sample += value;
         }
-        w += step;
     }
+    voices.retain(|voice| !voice.finished());
 }
 
 pub struct Player {
     stream: cpal::Stream,
-    pub commands: std::sync::mpsc::Sender<PlayerCommands>,
-    pub events: std::sync::mpsc::Receiver<PlayerEvents>,
+    pub sample_rate: u32,
+    pub synth: BasicSynth,
+    pub commands: SyncSender<PlayerCommands>,
+    pub events: Receiver<PlayerEvents>,
+}
+impl Player {
+    /// Plays the `parts` of `score` from `time`, the other parts are muted.
+    pub fn play(&self, score: &Score, parts: &HashSet<usize>, time: Time) {
+        let mut scheduler = Scheduler::new(score, self.sample_rate);
+        for part in 0..score.parts.len() {
+            scheduler.set_muted(part, !parts.contains(&part));
+        }
+        scheduler.seek(time);
+        scheduler.play();
+
+        let tempo = score.tempo();
+        let patches = score
+            .parts
+            .iter()
+            .map(|part| {
+                let note = SynthNote::from_note(&Note::default(), part, &tempo);
+                self.synth.patch(&note)
+            })
+            .collect();
+        let playback = Playback {
+            scheduler,
+            patches,
+            percussion: self.synth.percussion,
+        };
+        self.commands
+            .send(PlayerCommands::Play(Box::new(playback)))
+            .unwrap();
+    }
+    pub fn seek(&self, time: Time) { self.commands.send(PlayerCommands::Seek(time)).unwrap(); }
+    pub fn pause(&self) { self.commands.send(PlayerCommands::Pause).unwrap(); }
 }
 
 pub fn start_player() -> Player {
@@ -178,7 +306,7 @@ pub fn start_player() -> Player {
                 buf.fill(0.0);
                 state.update(buf, sample_rate, channels);
                 for sample in buf.iter_mut() {
-                    *sample = (*sample * 0.1).tanh();
+                    *sample = sample.tanh();
                 }
             },
             |e| {
@@ -190,6 +318,8 @@ pub fn start_player() -> Player {
 
     let player = Player {
         stream,
+        sample_rate,
+        synth: BasicSynth::default(),
         commands,
         events,
     };
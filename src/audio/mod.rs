@@ -0,0 +1,11 @@
+mod render;
+mod scheduler;
+mod soundfont;
+mod synth;
+mod wav;
+
+pub use render::*;
+pub use scheduler::*;
+pub use soundfont::*;
+pub use synth::*;
+pub use wav::*;
@@ -0,0 +1,123 @@
+use std::io;
+use std::path::Path;
+
+use super::{write_wav, Synth, SynthNote};
+use crate::score::Score;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartMix {
+    pub gain:  f32,
+    /// -1 is left, 1 is right.
+    pub pan:   f32,
+    pub muted: bool,
+}
+impl Default for PartMix {
+    fn default() -> Self {
+        PartMix {
+            gain:  1.0,
+            pan:   0.0,
+            muted: false,
+        }
+    }
+}
+impl PartMix {
+    /// Constant power panning.
+    pub fn channel_gains(&self) -> (f32, f32) {
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        (angle.cos() * self.gain, angle.sin() * self.gain)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub sample_rate: u32,
+    /// Mix settings per part, indexed like `Score::parts`. Missing entries use the default.
+    pub parts: Vec<PartMix>,
+    pub gain: f32,
+    /// Scale the result so the loudest sample is at full scale.
+    pub normalize: bool,
+}
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            sample_rate: 44100,
+            parts: Vec::new(),
+            gain: 1.0,
+            normalize: false,
+        }
+    }
+}
+
+/// Interleaved stereo audio.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioBuffer {
+    pub sample_rate: u32,
+    pub samples:     Vec<f32>,
+}
+impl AudioBuffer {
+    pub const CHANNELS: u16 = 2;
+
+    pub fn frames(&self) -> usize { self.samples.len() / AudioBuffer::CHANNELS as usize }
+    pub fn seconds(&self) -> f64 { self.frames() as f64 / self.sample_rate as f64 }
+    pub fn peak(&self) -> f32 { self.samples.iter().fold(0.0, |peak, s| peak.max(s.abs())) }
+
+    pub fn write_wav(&self, writer: impl io::Write) -> io::Result<()> {
+        write_wav(
+            writer,
+            &self.samples,
+            AudioBuffer::CHANNELS,
+            self.sample_rate,
+        )
+    }
+    pub fn save_wav(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_wav(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+}
+
+/// Renders the whole score offline.
+pub fn render(score: &Score, synth: &mut dyn Synth, options: &RenderOptions) -> AudioBuffer {
+    let tempo = score.tempo();
+    let sample_rate = options.sample_rate;
+    let mut buffer = AudioBuffer {
+        sample_rate,
+        samples: Vec::new(),
+    };
+    let mut scratch = Vec::new();
+
+    for (i, part) in score.parts.iter().enumerate() {
+        let mix = options.parts.get(i).copied().unwrap_or_default();
+        if mix.muted {
+            continue;
+        }
+        let (left, right) = mix.channel_gains();
+
+        for (_, note) in part.playback_notes() {
+            let synth_note = SynthNote::from_note(&note, part, &tempo);
+            let start = (tempo.seconds_at(note.time).max(0.0) * sample_rate as f64) as usize;
+            let length = synth_note.length.max(0.0) + synth.release_time(&synth_note);
+            let frames = (length * sample_rate as f64).ceil() as usize;
+
+            scratch.clear();
+            scratch.resize(frames, 0.0);
+            synth.render_note(&synth_note, sample_rate, &mut scratch);
+
+            let end = (start + frames) * AudioBuffer::CHANNELS as usize;
+            if buffer.samples.len() < end {
+                buffer.samples.resize(end, 0.0);
+            }
+            for (frame, sample) in scratch.iter().enumerate() {
+                let index = (start + frame) * AudioBuffer::CHANNELS as usize;
+                buffer.samples[index] += sample * left * options.gain;
+                buffer.samples[index + 1] += sample * right * options.gain;
+            }
+        }
+    }
+
+    if options.normalize {
+        let peak = buffer.peak();
+        if peak > 0.0 {
+            buffer.samples.iter_mut().for_each(|s| *s /= peak);
+        }
+    }
+    buffer
+}
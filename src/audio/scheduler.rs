@@ -0,0 +1,431 @@
+use std::ops::Range;
+
+use crate::note::articulation::Velocity;
+use crate::note::harmony::Pitch;
+use crate::note::rhythm::{TempoMap, Time, TimeRange};
+use crate::score::Score;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackEventKind {
+    NoteOn {
+        pitch:    Pitch,
+        velocity: Velocity,
+    },
+    NoteOff {
+        pitch: Pitch,
+    },
+    /// Bend of a sounding note in halfsteps.
+    Bend {
+        pitch:     Pitch,
+        halfsteps: f32,
+    },
+}
+impl PlaybackEventKind {
+    pub fn pitch(&self) -> Pitch {
+        match self {
+            PlaybackEventKind::NoteOn { pitch, .. }
+            | PlaybackEventKind::NoteOff { pitch }
+            | PlaybackEventKind::Bend { pitch, .. } => *pitch,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackEvent {
+    /// Offset into the current block in frames.
+    pub frame:   u32,
+    pub part:    usize,
+    /// Index into `Part::notes`. Ornaments written out from one note share its index.
+    pub note:    usize,
+    /// MIDI channel of the note, or of its part's instrument.
+    pub channel: u8,
+    pub kind:    PlaybackEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduledEvent {
+    seconds: f64,
+    part:    usize,
+    note:    usize,
+    channel: u8,
+    kind:    PlaybackEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduledNote {
+    start: f64,
+    end: f64,
+    part: usize,
+    note: usize,
+    channel: u8,
+    pitch: Pitch,
+    velocity: Velocity,
+}
+
+/// A sounding note, told apart from the others by what it sounds like rather than by the note
+/// it came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ActiveNote {
+    part:    usize,
+    note:    usize,
+    channel: u8,
+    pitch:   Pitch,
+}
+impl ActiveNote {
+    fn sounds_like(&self, other: &ActiveNote) -> bool {
+        self.part == other.part && self.channel == other.channel && self.pitch == other.pitch
+    }
+}
+
+/// Length of the stretches of the score the notes are grouped in for chasing.
+const CHASE_BUCKET_SECONDS: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct PartState {
+    muted: bool,
+    solo:  bool,
+}
+
+/// Turns a score into sample accurate note events for an audio callback.
+///
+/// Everything is prepared in `new`, so `process` never allocates and can run on the audio
+/// thread. Times are in seconds of the score as given by its tempo map, `tempo_scale` speeds
+/// up or slows down playback on top of that.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    tempo: TempoMap,
+    sample_rate: u32,
+    events: Vec<ScheduledEvent>,
+    /// Sorted by start, used to chase notes that are already sounding after a seek.
+    notes: Vec<ScheduledNote>,
+    /// Ranges of `bucket_notes` with the notes sounding in each `CHASE_BUCKET_SECONDS`.
+    buckets: Vec<Range<usize>>,
+    /// Indices into `notes`, sorted by start in each bucket.
+    bucket_notes: Vec<usize>,
+    parts: Vec<PartState>,
+    active: Vec<ActiveNote>,
+    /// Index of the next event to play.
+    cursor: usize,
+    position: f64,
+    loop_region: Option<(f64, f64)>,
+    tempo_scale: f64,
+    playing: bool,
+    /// Notes overlapping the position are restarted after a seek.
+    pub chase_notes: bool,
+    needs_seek: bool,
+    needs_mute: bool,
+}
+
+impl Scheduler {
+    pub fn new(score: &Score, sample_rate: u32) -> Self {
+        let tempo = score.tempo();
+        let mut events = Vec::new();
+        let mut notes = Vec::new();
+
+        for (p, part) in score.parts.iter().enumerate() {
+            let part_channel = part.instrument.as_ref().map_or(0, |i| i.channel);
+            for (n, note) in part.playback_notes() {
+                let start = tempo.seconds_at(note.time);
+                let end = tempo.seconds_at(note.time + note.duration);
+                let pitch = note.pitch;
+                let channel = note.channel.unwrap_or(part_channel);
+                notes.push(ScheduledNote {
+                    start,
+                    end,
+                    part: p,
+                    note: n,
+                    channel,
+                    pitch,
+                    velocity: note.velocity,
+                });
+                // Note offs go first so the same pitch can be restarted right away, except
+                // the ones of notes without length, which would hang otherwise
+                let mut event = |seconds, order, kind| {
+                    events.push((order, ScheduledEvent {
+                        seconds,
+                        part: p,
+                        note: n,
+                        channel,
+                        kind,
+                    }))
+                };
+                event(start, 1, PlaybackEventKind::NoteOn {
+                    pitch,
+                    velocity: note.velocity,
+                });
+                for (offset, halfsteps) in &note.bend {
+                    let seconds = tempo.seconds_at(note.time + (*offset - Time::ZERO));
+                    event(seconds.min(end), 2, PlaybackEventKind::Bend {
+                        pitch,
+                        halfsteps: *halfsteps,
+                    });
+                }
+                let order = if end > start { 0 } else { 3 };
+                event(end, order, PlaybackEventKind::NoteOff { pitch });
+            }
+        }
+
+        events.sort_by(|(a_order, a), (b_order, b)| {
+            a.seconds.total_cmp(&b.seconds).then(a_order.cmp(b_order))
+        });
+        let events = events
+            .into_iter()
+            .map(|(_, event)| event)
+            .collect::<Vec<_>>();
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        let bucket = |seconds: f64| (seconds.max(0.0) / CHASE_BUCKET_SECONDS) as usize;
+        let bucket_count = notes.iter().map(|n| bucket(n.end) + 1).max().unwrap_or(0);
+        let mut buckets = vec![Vec::new(); bucket_count];
+        for (i, note) in notes.iter().enumerate() {
+            if note.end > note.start {
+                for notes in &mut buckets[bucket(note.start)..=bucket(note.end)] {
+                    notes.push(i);
+                }
+            }
+        }
+        let mut bucket_notes = Vec::new();
+        let buckets = buckets
+            .into_iter()
+            .map(|notes| {
+                let range = bucket_notes.len()..bucket_notes.len() + notes.len();
+                bucket_notes.extend(notes);
+                range
+            })
+            .collect();
+
+        // Reserve enough room for the highest polyphony so `process` never allocates
+        let mut polyphony = 0usize;
+        let mut max_polyphony = 0;
+        for event in &events {
+            match event.kind {
+                PlaybackEventKind::NoteOn { .. } => polyphony += 1,
+                PlaybackEventKind::NoteOff { .. } => polyphony = polyphony.saturating_sub(1),
+                PlaybackEventKind::Bend { .. } => (),
+            }
+            max_polyphony = max_polyphony.max(polyphony);
+        }
+
+        Scheduler {
+            tempo,
+            sample_rate,
+            events,
+            notes,
+            buckets,
+            bucket_notes,
+            parts: vec![PartState::default(); score.parts.len()],
+            active: Vec::with_capacity(max_polyphony),
+            cursor: 0,
+            position: 0.0,
+            loop_region: None,
+            tempo_scale: 1.0,
+            playing: false,
+            chase_notes: true,
+            needs_seek: false,
+            needs_mute: false,
+        }
+    }
+
+    pub fn play(&mut self) { self.playing = true; }
+    /// Stops playback. Sounding notes are released in the next call to `process`.
+    pub fn pause(&mut self) {
+        self.playing = false;
+        self.needs_mute = true;
+    }
+    pub fn is_playing(&self) -> bool { self.playing }
+
+    pub fn position(&self) -> Time { self.tempo.time_at(self.position) }
+    pub fn position_seconds(&self) -> f64 { self.position }
+    pub fn seek(&mut self, time: Time) { self.seek_seconds(self.tempo.seconds_at(time)); }
+    pub fn seek_seconds(&mut self, seconds: f64) {
+        self.position = seconds;
+        self.needs_seek = true;
+    }
+
+    /// Loops playback inside `region`, `None` plays to the end.
+    pub fn set_loop(&mut self, region: Option<TimeRange>) {
+        self.loop_region = region
+            .map(|r| (self.tempo.seconds_at(r.start), self.tempo.seconds_at(r.end)))
+            .filter(|(start, end)| start < end);
+    }
+    /// 2 plays twice as fast as the tempo map says.
+    pub fn set_tempo_scale(&mut self, scale: f64) {
+        if scale > 0.0 {
+            self.tempo_scale = scale;
+        }
+    }
+    pub fn set_muted(&mut self, part: usize, muted: bool) {
+        if let Some(state) = self.parts.get_mut(part) {
+            state.muted = muted;
+            self.needs_mute = true;
+        }
+    }
+    pub fn set_solo(&mut self, part: usize, solo: bool) {
+        if let Some(state) = self.parts.get_mut(part) {
+            state.solo = solo;
+            self.needs_mute = true;
+        }
+    }
+    pub fn is_audible(&self, part: usize) -> bool {
+        let any_solo = self.parts.iter().any(|p| p.solo);
+        self.parts
+            .get(part)
+            .is_some_and(|p| !p.muted && (p.solo || !any_solo))
+    }
+
+    /// Advances playback by `frames` and calls `emit` for every event in this block, in order.
+    pub fn process(&mut self, frames: u32, mut emit: impl FnMut(PlaybackEvent)) {
+        if self.needs_mute {
+            self.needs_mute = false;
+            let playing = self.playing;
+            self.release_where(0, &mut emit, |s, part| !playing || !s.is_audible(part));
+        }
+        if !self.playing {
+            return;
+        }
+        if self.needs_seek {
+            self.needs_seek = false;
+            self.release_where(0, &mut emit, |_, _| true);
+            self.jump(self.position, 0, &mut emit);
+        }
+
+        let seconds_per_frame = self.tempo_scale / self.sample_rate as f64;
+        let mut frame = 0u32;
+        while frame < frames {
+            let mut end = self.position + (frames - frame) as f64 * seconds_per_frame;
+            let loop_end = self
+                .loop_region
+                .filter(|(_, loop_end)| self.position < *loop_end);
+            if let Some((_, loop_end)) = loop_end {
+                end = end.min(loop_end);
+            }
+
+            while let Some(event) = self.events.get(self.cursor).copied() {
+                if event.seconds >= end {
+                    break;
+                }
+                self.cursor += 1;
+                let offset =
+                    ((event.seconds - self.position).max(0.0) / seconds_per_frame).round() as u32;
+                self.dispatch(event, (frame + offset).min(frames - 1), &mut emit);
+            }
+
+            let advanced = ((end - self.position) / seconds_per_frame).round() as u32;
+            frame += advanced.max(1);
+            self.position = end;
+
+            if let Some((loop_start, loop_end)) = loop_end {
+                if self.position >= loop_end {
+                    let at = frame.min(frames - 1);
+                    self.release_where(at, &mut emit, |_, _| true);
+                    self.jump(loop_start, at, &mut emit);
+                }
+            }
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        event: ScheduledEvent,
+        frame: u32,
+        emit: &mut impl FnMut(PlaybackEvent),
+    ) {
+        let active = ActiveNote {
+            part:    event.part,
+            note:    event.note,
+            channel: event.channel,
+            pitch:   event.kind.pitch(),
+        };
+        let sounding = self.active.iter().position(|a| a.sounds_like(&active));
+        match event.kind {
+            PlaybackEventKind::NoteOn { .. } => {
+                // Without a free slot the note could never be released
+                if !self.is_audible(event.part)
+                    || sounding.is_some()
+                    || self.active.len() == self.active.capacity()
+                {
+                    return;
+                }
+                self.active.push(active);
+            }
+            PlaybackEventKind::NoteOff { .. } => {
+                let Some(sounding) = sounding
+                else {
+                    return;
+                };
+                self.active.swap_remove(sounding);
+            }
+            PlaybackEventKind::Bend { .. } => {
+                if sounding.is_none() {
+                    return;
+                }
+            }
+        }
+        emit(PlaybackEvent {
+            frame,
+            part: event.part,
+            note: event.note,
+            channel: event.channel,
+            kind: event.kind,
+        });
+    }
+
+    /// Moves to `seconds` and restarts notes sounding there.
+    fn jump(&mut self, seconds: f64, frame: u32, emit: &mut impl FnMut(PlaybackEvent)) {
+        self.position = seconds;
+        self.cursor = self.events.partition_point(|e| e.seconds < seconds);
+        if !self.chase_notes {
+            return;
+        }
+        let bucket = (seconds.max(0.0) / CHASE_BUCKET_SECONDS) as usize;
+        let Some(range) = self.buckets.get(bucket).cloned()
+        else {
+            return;
+        };
+        for i in range {
+            let note = self.notes[self.bucket_notes[i]];
+            if note.start < seconds && note.end > seconds {
+                self.dispatch(
+                    ScheduledEvent {
+                        seconds,
+                        part: note.part,
+                        note: note.note,
+                        channel: note.channel,
+                        kind: PlaybackEventKind::NoteOn {
+                            pitch:    note.pitch,
+                            velocity: note.velocity,
+                        },
+                    },
+                    frame,
+                    emit,
+                );
+            }
+        }
+    }
+
+    fn release_where(
+        &mut self,
+        frame: u32,
+        emit: &mut impl FnMut(PlaybackEvent),
+        release: impl Fn(&Self, usize) -> bool,
+    ) {
+        let mut i = 0;
+        while i < self.active.len() {
+            let active = self.active[i];
+            if !release(self, active.part) {
+                i += 1;
+                continue;
+            }
+            self.active.swap_remove(i);
+            emit(PlaybackEvent {
+                frame,
+                part: active.part,
+                note: active.note,
+                channel: active.channel,
+                kind: PlaybackEventKind::NoteOff {
+                    pitch: active.pitch,
+                },
+            });
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use super::{Synth, SynthNote};
+
+#[derive(Debug)]
+pub enum SoundFontError {
+    Io(io::Error),
+    InvalidFormat(&'static str),
+}
+impl From<io::Error> for SoundFontError {
+    fn from(e: io::Error) -> Self { SoundFontError::Io(e) }
+}
+
+/// A volume envelope in seconds, sustain as gain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeEnvelope {
+    pub delay:   f64,
+    pub attack:  f64,
+    pub hold:    f64,
+    pub decay:   f64,
+    pub sustain: f32,
+    pub release: f64,
+}
+impl VolumeEnvelope {
+    /// The gain at `seconds` after the start of a note that's released after `length` seconds.
+    pub fn gain(&self, seconds: f64, length: f64) -> f32 {
+        let held = |t: f64| -> f32 {
+            let t = t - self.delay;
+            if t < 0.0 {
+                0.0
+            }
+            else if t < self.attack {
+                (t / self.attack) as f32
+            }
+            else if t < self.attack + self.hold {
+                1.0
+            }
+            else if t < self.attack + self.hold + self.decay {
+                // Linear in decibels
+                let f = (t - self.attack - self.hold) / self.decay;
+                self.sustain.powf(f as f32)
+            }
+            else {
+                self.sustain
+            }
+        };
+        if seconds < length {
+            return held(seconds);
+        }
+        let f = (seconds - length) / self.release.max(0.001);
+        if f >= 1.0 {
+            0.0
+        }
+        else {
+            // Fall by 100dB over the release time
+            held(length) * 10f32.powf(-5.0 * f as f32)
+        }
+    }
+}
+
+/// A sample with the key and velocity range it's played for. Combines the generators of a
+/// preset zone and an instrument zone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub keys: RangeInclusive<u8>,
+    pub velocities: RangeInclusive<u8>,
+    /// Offsets into `SoundFont::samples`.
+    pub start: usize,
+    pub end: usize,
+    pub loop_start: usize,
+    pub loop_end: usize,
+    pub looping: bool,
+    /// Loop only until the note is released, then play the rest of the sample.
+    pub loop_until_release: bool,
+    pub sample_rate: u32,
+    pub root_key: u8,
+    pub tune_cents: f32,
+    pub gain: f32,
+    pub envelope: VolumeEnvelope,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name:    String,
+    pub bank:    u16,
+    pub program: u16,
+    pub regions: Vec<Region>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SoundFont {
+    pub name:    String,
+    pub presets: Vec<Preset>,
+    /// All sample data, normalized to -1 to 1.
+    pub samples: Vec<f32>,
+}
+
+impl SoundFont {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SoundFontError> {
+        SoundFont::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SoundFontError> {
+        let (id, body) = chunk(data)?.0;
+        if id != *b"RIFF" || body.get(..4) != Some(b"sfbk") {
+            return Err(SoundFontError::InvalidFormat("not a soundfont"));
+        }
+
+        let mut font = SoundFont::default();
+        let mut pdta = None;
+        for (id, list) in chunks(&body[4..])? {
+            if id != *b"LIST" || list.len() < 4 {
+                continue;
+            }
+            for (id, data) in chunks(&list[4..])? {
+                match (&list[..4], &id) {
+                    (b"INFO", b"INAM") => font.name = string(data),
+                    (b"sdta", b"smpl") => {
+                        font.samples = data
+                            .chunks_exact(2)
+                            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                            .collect();
+                    }
+                    (b"pdta", _) => {
+                        pdta.get_or_insert_with(BTreeMap::new).insert(id, data);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let pdta = pdta.ok_or(SoundFontError::InvalidFormat("missing pdta chunk"))?;
+        let get = |id: &[u8; 4]| {
+            pdta.get(id)
+                .copied()
+                .ok_or(SoundFontError::InvalidFormat("missing pdta sub chunk"))
+        };
+        let phdr = records(get(b"phdr")?, 38);
+        let pbag = records(get(b"pbag")?, 4);
+        let pgen = records(get(b"pgen")?, 4);
+        let inst = records(get(b"inst")?, 22);
+        let ibag = records(get(b"ibag")?, 4);
+        let igen = records(get(b"igen")?, 4);
+        let shdr = records(get(b"shdr")?, 46);
+
+        let zones = |bags: &[&[u8]], gens: &[&[u8]], first: usize, last: usize| {
+            (first..last)
+                .filter_map(|bag| {
+                    let from = u16_at(bags.get(bag)?, 0) as usize;
+                    let to = u16_at(bags.get(bag + 1)?, 0) as usize;
+                    Some(
+                        gens.get(from..to)?
+                            .iter()
+                            .map(|g| (u16_at(g, 0), u16_at(g, 2)))
+                            .collect::<Generators>(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Instruments as lists of zones, with the global zone merged into each
+        let instruments = inst
+            .windows(2)
+            .map(|w| {
+                let zones = zones(
+                    &ibag,
+                    &igen,
+                    u16_at(w[0], 20) as usize,
+                    u16_at(w[1], 20) as usize,
+                );
+                with_global(zones, SAMPLE_ID)
+            })
+            .collect::<Vec<_>>();
+
+        for w in phdr.windows(2) {
+            let mut preset = Preset {
+                name:    string(&w[0][..20]),
+                program: u16_at(w[0], 20),
+                bank:    u16_at(w[0], 22),
+                regions: Vec::new(),
+            };
+            let preset_zones = with_global(
+                zones(
+                    &pbag,
+                    &pgen,
+                    u16_at(w[0], 24) as usize,
+                    u16_at(w[1], 24) as usize,
+                ),
+                INSTRUMENT,
+            );
+            for preset_zone in &preset_zones {
+                let Some(instrument) = preset_zone
+                    .get(&INSTRUMENT)
+                    .and_then(|i| instruments.get(*i as usize))
+                else {
+                    continue;
+                };
+                for zone in instrument {
+                    let Some(header) = zone.get(&SAMPLE_ID).and_then(|s| shdr.get(*s as usize))
+                    else {
+                        continue;
+                    };
+                    if let Some(region) = region(preset_zone, zone, header, font.samples.len()) {
+                        preset.regions.push(region);
+                    }
+                }
+            }
+            font.presets.push(preset);
+        }
+
+        Ok(font)
+    }
+
+    pub fn preset(&self, bank: u16, program: u16) -> Option<&Preset> {
+        self.presets
+            .iter()
+            .find(|p| p.bank == bank && p.program == program)
+    }
+}
+
+type Generators = BTreeMap<u16, u16>;
+
+const START_OFFSET: u16 = 0;
+const END_OFFSET: u16 = 1;
+const LOOP_START_OFFSET: u16 = 2;
+const LOOP_END_OFFSET: u16 = 3;
+const START_COARSE_OFFSET: u16 = 4;
+const END_COARSE_OFFSET: u16 = 12;
+const DELAY_VOL_ENV: u16 = 33;
+const ATTACK_VOL_ENV: u16 = 34;
+const HOLD_VOL_ENV: u16 = 35;
+const DECAY_VOL_ENV: u16 = 36;
+const SUSTAIN_VOL_ENV: u16 = 37;
+const RELEASE_VOL_ENV: u16 = 38;
+const INSTRUMENT: u16 = 41;
+const KEY_RANGE: u16 = 43;
+const VEL_RANGE: u16 = 44;
+const LOOP_START_COARSE_OFFSET: u16 = 45;
+const INITIAL_ATTENUATION: u16 = 48;
+const LOOP_END_COARSE_OFFSET: u16 = 50;
+const COARSE_TUNE: u16 = 51;
+const FINE_TUNE: u16 = 52;
+const SAMPLE_ID: u16 = 53;
+const SAMPLE_MODES: u16 = 54;
+const OVERRIDING_ROOT_KEY: u16 = 58;
+
+/// A zone without the terminal generator (`INSTRUMENT` or `SAMPLE_ID`) in the first position
+/// is the global zone and provides defaults for all other zones.
+fn with_global(mut zones: Vec<Generators>, terminal: u16) -> Vec<Generators> {
+    if zones.first().is_some_and(|z| !z.contains_key(&terminal)) {
+        let global = zones.remove(0);
+        for zone in &mut zones {
+            for (k, v) in &global {
+                zone.entry(*k).or_insert(*v);
+            }
+        }
+    }
+    zones
+}
+
+fn region(
+    preset: &Generators,
+    instrument: &Generators,
+    header: &[u8],
+    len: usize,
+) -> Option<Region> {
+    let signed = |gens: &Generators, id: u16, default: i32| {
+        gens.get(&id).map(|v| *v as i16 as i32).unwrap_or(default)
+    };
+    // Preset generators are added on top of the instrument's
+    let sum = |id: u16, default: i32| signed(instrument, id, default) + signed(preset, id, 0);
+    let offset = |fine: u16, coarse: u16| {
+        signed(instrument, fine, 0) + signed(instrument, coarse, 0) * 32768
+    };
+    let address = |base: usize, fine: u16, coarse: u16| {
+        (base as i64 + offset(fine, coarse) as i64).clamp(0, len as i64) as usize
+    };
+    let range = |id: u16| {
+        let range = |gens: &Generators| {
+            gens.get(&id)
+                .map(|v| (*v as u8, (*v >> 8) as u8))
+                .unwrap_or((0, 127))
+        };
+        let (a, b) = (range(instrument), range(preset));
+        a.0.max(b.0)..=a.1.min(b.1)
+    };
+    let seconds = |id: u16| 2f64.powf(sum(id, -12000) as f64 / 1200.0);
+
+    let start = address(
+        u32_at(header, 20) as usize,
+        START_OFFSET,
+        START_COARSE_OFFSET,
+    );
+    let end = address(u32_at(header, 24) as usize, END_OFFSET, END_COARSE_OFFSET);
+    if start >= end {
+        return None;
+    }
+    let original_pitch = header[40];
+    let pitch_correction = header[41] as i8;
+    let root_key = match signed(instrument, OVERRIDING_ROOT_KEY, -1) {
+        key @ 0..=127 => key as u8,
+        _ if original_pitch <= 127 => original_pitch,
+        _ => 60,
+    };
+    let modes = signed(instrument, SAMPLE_MODES, 0) & 3;
+
+    Some(Region {
+        keys: range(KEY_RANGE),
+        velocities: range(VEL_RANGE),
+        start,
+        end,
+        loop_start: address(
+            u32_at(header, 28) as usize,
+            LOOP_START_OFFSET,
+            LOOP_START_COARSE_OFFSET,
+        ),
+        loop_end: address(
+            u32_at(header, 32) as usize,
+            LOOP_END_OFFSET,
+            LOOP_END_COARSE_OFFSET,
+        ),
+        looping: modes == 1 || modes == 3,
+        loop_until_release: modes == 3,
+        sample_rate: u32_at(header, 36).max(1),
+        root_key,
+        tune_cents: (sum(COARSE_TUNE, 0) * 100 + sum(FINE_TUNE, 0) + pitch_correction as i32)
+            as f32,
+        gain: 10f32.powf(-(sum(INITIAL_ATTENUATION, 0).max(0) as f32) / 200.0),
+        envelope: VolumeEnvelope {
+            delay:   seconds(DELAY_VOL_ENV),
+            attack:  seconds(ATTACK_VOL_ENV),
+            hold:    seconds(HOLD_VOL_ENV),
+            decay:   seconds(DECAY_VOL_ENV),
+            sustain: 10f32.powf(-(sum(SUSTAIN_VOL_ENV, 0).clamp(0, 1440) as f32) / 200.0),
+            release: seconds(RELEASE_VOL_ENV),
+        },
+    })
+}
+
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+/// Reads one RIFF chunk, returns it and the rest of the data.
+fn chunk(data: &[u8]) -> Result<(Chunk<'_>, &[u8]), SoundFontError> {
+    if data.len() < 8 {
+        return Err(SoundFontError::InvalidFormat("truncated chunk header"));
+    }
+    let id = [data[0], data[1], data[2], data[3]];
+    let len = u32_at(data, 4) as usize;
+    let body = data
+        .get(8..8 + len)
+        .ok_or(SoundFontError::InvalidFormat("truncated chunk"))?;
+    // Chunks are padded to an even length
+    let rest = data.get(8 + len + len % 2..).unwrap_or(&[]);
+    Ok(((id, body), rest))
+}
+
+fn chunks(mut data: &[u8]) -> Result<Vec<Chunk<'_>>, SoundFontError> {
+    let mut result = Vec::new();
+    while !data.is_empty() {
+        let (c, rest) = chunk(data)?;
+        result.push(c);
+        data = rest;
+    }
+    Ok(result)
+}
+
+fn records(data: &[u8], size: usize) -> Vec<&[u8]> { data.chunks_exact(size).collect() }
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+fn string(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+/// The bank General MIDI drum kits are in.
+pub const PERCUSSION_BANK: u16 = 128;
+
+/// Plays notes with the samples of a soundfont.
+///
+/// Parts are mapped to presets by their General MIDI program in bank 0, percussion uses bank 128.
+/// `programs` overrides the mapping for single melodic programs. Missing presets fall back to
+/// program 0 of the bank, then to any preset of the same kind, so drums stay silent in a
+/// soundfont without drum kits.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    pub font:     SoundFont,
+    /// Maps a General MIDI program to a `(bank, program)` preset of the soundfont.
+    pub programs: BTreeMap<u8, (u16, u16)>,
+}
+
+impl Sampler {
+    pub fn new(font: SoundFont) -> Self {
+        Sampler {
+            font,
+            programs: BTreeMap::new(),
+        }
+    }
+
+    pub fn preset_for(&self, note: &SynthNote) -> Option<&Preset> {
+        let (bank, program) = match self.programs.get(&note.program) {
+            _ if note.percussion => (PERCUSSION_BANK, 0),
+            Some(mapped) => *mapped,
+            None => (0, note.program as u16),
+        };
+        let drums = bank == PERCUSSION_BANK;
+        self.font
+            .preset(bank, program)
+            .or_else(|| self.font.preset(bank, 0))
+            // Never play drums with a melodic preset or the other way round
+            .or_else(|| {
+                self.font
+                    .presets
+                    .iter()
+                    .find(|p| (p.bank == PERCUSSION_BANK) == drums)
+            })
+    }
+
+    pub fn regions_for<'a>(&'a self, note: &SynthNote) -> impl Iterator<Item = &'a Region> + 'a {
+        let key = note.pitch.to_midi().clamp(0, 127) as u8;
+        let velocity = (note.velocity * 127.0).round().clamp(0.0, 127.0) as u8;
+        self.preset_for(note)
+            .into_iter()
+            .flat_map(|preset| &preset.regions)
+            .filter(move |r| r.keys.contains(&key) && r.velocities.contains(&velocity))
+    }
+}
+
+impl Synth for Sampler {
+    fn release_time(&self, note: &SynthNote) -> f64 {
+        self.regions_for(note)
+            .map(|r| r.envelope.release)
+            .fold(0.0, f64::max)
+    }
+
+    fn render_note(&mut self, note: &SynthNote, sample_rate: u32, out: &mut [f32]) {
+        let samples = &self.font.samples;
+        let dt = 1.0 / sample_rate as f64;
+
+        for region in self.regions_for(note) {
+            let loop_len = region.loop_end.saturating_sub(region.loop_start);
+            let mut position = region.start as f64;
+
+            for (i, sample) in out.iter_mut().enumerate() {
+                let t = i as f64 * dt;
+                let looping = region.looping
+                    && loop_len > 0
+                    && (!region.loop_until_release || t < note.length);
+                if looping && position >= region.loop_end as f64 {
+                    position -= loop_len as f64;
+                }
+                let index = position as usize;
+                // The sample after the loop end is the loop start
+                let next = if looping && index + 1 >= region.loop_end {
+                    region.loop_start
+                }
+                else {
+                    index + 1
+                };
+                if next >= region.end || index >= samples.len() {
+                    break;
+                }
+
+                // Linear interpolation between neighbouring samples
+                let f = (position - index as f64) as f32;
+                let value = samples[index] * (1.0 - f) + samples[next] * f;
+                let gain = region.envelope.gain(t, note.length) * region.gain * note.velocity;
+                *sample += value * gain;
+
+                let cents = (note.pitch.0 + note.bend_at(t) - region.root_key as f32) * 100.0
+                    + region.tune_cents;
+                let ratio = 2f64.powf(cents as f64 / 1200.0) * region.sample_rate as f64
+                    / sample_rate as f64;
+                position += ratio;
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+
+use crate::instrument::drums::GM_PERCUSSION_CHANNEL;
+use crate::note::harmony::{Interval, Pitch};
+use crate::note::rhythm::{TempoMap, Time};
+use crate::note::Note;
+use crate::score::Part;
+
+/// A note as a synthesizer sees it, with all timing in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SynthNote {
+    pub pitch: Pitch,
+    /// 0 to 1.
+    pub velocity: f32,
+    /// Seconds until the note is released.
+    pub length: f64,
+    /// Pitch bend in halfsteps over seconds since the start of the note.
+    pub bend: Vec<(f64, f32)>,
+    /// 0 lets the note ring freely, 1 mutes it right away.
+    pub damping: f32,
+    /// General MIDI program of the part.
+    pub program: u8,
+    pub percussion: bool,
+}
+
+impl SynthNote {
+    /// Bend points in `Note::bend` are relative to the start of the note.
+    pub fn from_note(note: &Note, part: &Part, tempo: &TempoMap) -> Self {
+        let start = tempo.seconds_at(note.time);
+        let instrument = part.instrument.as_ref();
+        SynthNote {
+            pitch: note.pitch,
+            velocity: note.velocity.to_f32(),
+            length: tempo.seconds_between(note.time, note.duration),
+            bend: note
+                .bend
+                .iter()
+                .map(|(offset, bend)| {
+                    let time = note.time + (*offset - Time::ZERO);
+                    (tempo.seconds_at(time) - start, *bend)
+                })
+                .collect(),
+            damping: note.damping.map(|d| d.to_f32()).unwrap_or(0.0),
+            program: instrument.map(|i| i.program).unwrap_or(0),
+            percussion: instrument.is_some_and(|i| i.is_percussion())
+                || note.channel == Some(GM_PERCUSSION_CHANNEL),
+        }
+    }
+
+    /// The bend in halfsteps at `seconds` after the start, linearly interpolated.
+    pub fn bend_at(&self, seconds: f64) -> f32 {
+        let i = self.bend.partition_point(|(t, _)| *t <= seconds);
+        match (
+            i.checked_sub(1).map(|i| self.bend[i]),
+            self.bend.get(i).copied(),
+        ) {
+            (None, None) => 0.0,
+            (Some((_, bend)), None) | (None, Some((_, bend))) => bend,
+            (Some((t0, b0)), Some((t1, b1))) => {
+                let f = ((seconds - t0) / (t1 - t0)) as f32;
+                b0 + (b1 - b0) * f
+            }
+        }
+    }
+    /// Frequency at `seconds` after the start, including bend.
+    pub fn frequency_at(&self, seconds: f64) -> f64 {
+        (self.pitch + Interval(self.bend_at(seconds))).frequency_hertz() as f64
+    }
+}
+
+/// Something that can turn notes into sound.
+pub trait Synth {
+    /// How long `note` keeps sounding after it's released.
+    fn release_time(&self, note: &SynthNote) -> f64;
+    /// Adds the mono signal of `note` to `out`. `out[0]` is the start of the note and `out` is
+    /// long enough to fit the note and its release.
+    fn render_note(&mut self, note: &SynthNote, sample_rate: u32, out: &mut [f32]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Square,
+    Sawtooth,
+    Noise,
+}
+
+/// Attack, decay and release in seconds, sustain as a level from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    pub attack:  f64,
+    pub decay:   f64,
+    pub sustain: f32,
+    pub release: f64,
+}
+impl Envelope {
+    pub const ORGAN: Envelope = Envelope {
+        attack:  0.005,
+        decay:   0.0,
+        sustain: 1.0,
+        release: 0.02,
+    };
+    pub const PIANO: Envelope = Envelope {
+        attack:  0.002,
+        decay:   1.5,
+        sustain: 0.2,
+        release: 0.3,
+    };
+    pub const PLUCK: Envelope = Envelope {
+        attack:  0.001,
+        decay:   0.4,
+        sustain: 0.0,
+        release: 0.1,
+    };
+    pub const PAD: Envelope = Envelope {
+        attack:  0.3,
+        decay:   0.5,
+        sustain: 0.8,
+        release: 0.8,
+    };
+    pub const HIT: Envelope = Envelope {
+        attack:  0.0005,
+        decay:   0.15,
+        sustain: 0.0,
+        release: 0.05,
+    };
+
+    /// The level at `seconds` after the start of a note that's released after `length` seconds.
+    pub fn level(&self, seconds: f64, length: f64) -> f32 {
+        let held = |t: f64| -> f32 {
+            if t < self.attack {
+                (t / self.attack) as f32
+            }
+            else if t < self.attack + self.decay {
+                let f = ((t - self.attack) / self.decay) as f32;
+                1.0 + (self.sustain - 1.0) * f
+            }
+            else {
+                self.sustain
+            }
+        };
+        if seconds < length {
+            held(seconds)
+        }
+        else if self.release > 0.0 {
+            let f = ((seconds - length) / self.release) as f32;
+            held(length) * (1.0 - f).max(0.0)
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Patch {
+    pub waveform: Waveform,
+    pub envelope: Envelope,
+    pub gain:     f32,
+}
+impl Patch {
+    pub fn new(waveform: Waveform, envelope: Envelope) -> Self {
+        Patch {
+            waveform,
+            envelope,
+            gain: 0.3,
+        }
+    }
+}
+
+/// A simple oscillator synthesizer with one patch per General MIDI program.
+#[derive(Debug, Clone)]
+pub struct BasicSynth {
+    pub default: Patch,
+    pub programs: BTreeMap<u8, Patch>,
+    pub percussion: Patch,
+    noise: u32,
+}
+
+impl Default for BasicSynth {
+    fn default() -> Self {
+        let mut programs = BTreeMap::new();
+        // Piano, chromatic percussion
+        for program in 0..16 {
+            programs.insert(program, Patch::new(Waveform::Triangle, Envelope::PIANO));
+        }
+        // Organs
+        for program in 16..24 {
+            programs.insert(program, Patch::new(Waveform::Square, Envelope::ORGAN));
+        }
+        // Guitars, basses
+        for program in 24..40 {
+            programs.insert(program, Patch::new(Waveform::Sawtooth, Envelope::PLUCK));
+        }
+        // Strings, ensembles, pads
+        for program in (40..56).chain(88..96) {
+            programs.insert(program, Patch::new(Waveform::Sawtooth, Envelope::PAD));
+        }
+
+        BasicSynth {
+            default: Patch::new(Waveform::Sine, Envelope::ORGAN),
+            programs,
+            percussion: Patch::new(Waveform::Noise, Envelope::HIT),
+            noise: 0x2545_f491,
+        }
+    }
+}
+
+impl BasicSynth {
+    /// A synth that plays everything with the same patch.
+    pub fn with_patch(patch: Patch) -> Self {
+        BasicSynth {
+            default: patch,
+            programs: BTreeMap::new(),
+            ..BasicSynth::default()
+        }
+    }
+
+    pub fn patch(&self, note: &SynthNote) -> Patch {
+        if note.percussion {
+            return self.percussion;
+        }
+        self.programs
+            .get(&note.program)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    fn next_noise(&mut self) -> f32 {
+        // xorshift32
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+impl Synth for BasicSynth {
+    fn release_time(&self, note: &SynthNote) -> f64 { self.patch(note).envelope.release }
+
+    fn render_note(&mut self, note: &SynthNote, sample_rate: u32, out: &mut [f32]) {
+        let patch = self.patch(note);
+        let dt = 1.0 / sample_rate as f64;
+        // Damped notes die away exponentially
+        let damping = note.damping as f64 * 20.0;
+
+        let mut phase = 0.0f64;
+        for (i, sample) in out.iter_mut().enumerate() {
+            let t = i as f64 * dt;
+            let level = patch.envelope.level(t, note.length);
+            if level <= 0.0 && t > note.length {
+                break;
+            }
+
+            let value = match patch.waveform {
+                Waveform::Sine => (phase * TAU).sin() as f32,
+                Waveform::Triangle => (1.0 - 4.0 * (phase - 0.5).abs()) as f32,
+                Waveform::Square => {
+                    if phase < 0.5 {
+                        1.0
+                    }
+                    else {
+                        -1.0
+                    }
+                }
+                Waveform::Sawtooth => (2.0 * phase - 1.0) as f32,
+                Waveform::Noise => self.next_noise(),
+            };
+            let damped = (-damping * t).exp() as f32;
+            *sample += value * level * damped * note.velocity * patch.gain;
+
+            phase = (phase + note.frequency_at(t) * dt).fract();
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::io::{self, Write};
+
+/// Writes interleaved samples as a 16 bit PCM wav file. Samples are clipped to -1 to 1.
+pub fn write_wav(
+    mut writer: impl Write,
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+) -> io::Result<()> {
+    let bytes_per_sample = 2u16;
+    let data_len = samples.len() as u32 * bytes_per_sample as u32;
+    let block_align = channels * bytes_per_sample;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&(bytes_per_sample * 8).to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    let mut data = Vec::with_capacity(data_len as usize);
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    writer.write_all(&data)
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use super::fretted::FrettedTuning;
+use crate::note::articulation::Finger;
+use crate::note::harmony::Chord;
+
+/// One finger pressing several strings at the same fret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Barre {
+    pub fret: u8,
+    pub first_string: u8,
+    pub last_string: u8,
+    pub finger: Finger,
+}
+
+/// A way to play a chord on a fretted instrument.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Voicing {
+    /// The fret of each string, `None` for muted strings. Indexed like `FrettedTuning::strings`.
+    pub frets:   Vec<Option<u8>>,
+    /// The fretting finger of each string, `None` for open and muted strings.
+    pub fingers: Vec<Option<Finger>>,
+    pub barre:   Option<Barre>,
+    /// Lower is easier to play.
+    pub cost:    f32,
+}
+impl Voicing {
+    pub fn fretted(&self, tuning: &FrettedTuning) -> impl Iterator<Item = (u8, u8)> + '_ {
+        let open = (0..self.frets.len() as u8)
+            .map(|string| tuning.open_fret(string))
+            .collect::<Vec<_>>();
+        self.frets
+            .iter()
+            .enumerate()
+            .filter_map(move |(string, fret)| Some((string as u8, (*fret)?)))
+            .filter(move |(string, fret)| *fret != open[*string as usize])
+    }
+    pub fn lowest_fret(&self, tuning: &FrettedTuning) -> Option<u8> {
+        self.fretted(tuning).map(|(_, fret)| fret).min()
+    }
+    pub fn highest_fret(&self, tuning: &FrettedTuning) -> Option<u8> {
+        self.fretted(tuning).map(|(_, fret)| fret).max()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoicingOptions {
+    /// Highest fret any finger may press.
+    pub max_fret: u8,
+    /// Number of frets the hand can span, including the lowest one.
+    pub max_span: u8,
+    /// Minimum number of sounding strings.
+    pub min_strings: usize,
+    /// Only accept voicings with the root in the bass.
+    pub root_in_bass: bool,
+    /// Allow leaving out the fifth when the chord has more than three notes.
+    pub allow_no_fifth: bool,
+}
+impl Default for VoicingOptions {
+    fn default() -> Self {
+        VoicingOptions {
+            max_fret: 12,
+            max_span: 4,
+            min_strings: 3,
+            root_in_bass: false,
+            allow_no_fifth: true,
+        }
+    }
+}
+
+/// All playable voicings of `chord`, easiest first.
+pub fn voicings(tuning: &FrettedTuning, chord: &Chord, options: VoicingOptions) -> Vec<Voicing> {
+    let strings = tuning.strings.len() as u8;
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+
+    for position in 1..=options
+        .max_fret
+        .saturating_sub(options.max_span.saturating_sub(1))
+        .max(1)
+    {
+        // Candidate frets per string: muted, open or inside the hand span
+        let candidates = (0..strings)
+            .map(|string| {
+                let open = tuning.open_fret(string);
+                let frets = std::iter::once(open)
+                    .chain((position..position + options.max_span).filter(|f| *f > open))
+                    .filter(|fret| {
+                        tuning
+                            .pitch_at(string, *fret)
+                            .is_some_and(|pitch| chord.contains(pitch))
+                    })
+                    .map(Some);
+                std::iter::once(None).chain(frets).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut frets = vec![None; strings as usize];
+        enumerate(&candidates, 0, &mut frets, &mut |frets| {
+            if seen.contains(frets) {
+                return;
+            }
+            if let Some(voicing) = rate(tuning, chord, &options, frets) {
+                seen.insert(frets.to_vec());
+                result.push(voicing);
+            }
+        });
+    }
+
+    result.sort_by(|a, b| a.cost.total_cmp(&b.cost));
+    result
+}
+
+fn enumerate(
+    candidates: &[Vec<Option<u8>>],
+    string: usize,
+    frets: &mut Vec<Option<u8>>,
+    f: &mut impl FnMut(&[Option<u8>]),
+) {
+    if string == candidates.len() {
+        f(frets);
+        return;
+    }
+    for fret in &candidates[string] {
+        frets[string] = *fret;
+        enumerate(candidates, string + 1, frets, f);
+    }
+}
+
+fn rate(
+    tuning: &FrettedTuning,
+    chord: &Chord,
+    options: &VoicingOptions,
+    frets: &[Option<u8>],
+) -> Option<Voicing> {
+    let sounding = frets
+        .iter()
+        .enumerate()
+        .filter_map(|(string, fret)| tuning.pitch_at(string as u8, (*fret)?))
+        .collect::<Vec<_>>();
+    if sounding.len() < options.min_strings {
+        return None;
+    }
+
+    // Every chord tone must sound, except maybe the fifth
+    let classes = sounding
+        .iter()
+        .map(|p| p.to_midi().rem_euclid(12) as u8)
+        .collect::<HashSet<_>>();
+    let root = (chord.root.to_midi_chroma() as i32).rem_euclid(12) as u8;
+    let fifth = (root + 7) % 12;
+    let missing = chord
+        .pitch_classes()
+        .filter(|c| !classes.contains(c))
+        .collect::<Vec<_>>();
+    let omits_fifth = missing == [fifth] && options.allow_no_fifth && chord.intervals.len() > 2;
+    if !missing.is_empty() && !omits_fifth {
+        return None;
+    }
+
+    let bass = *sounding.iter().min_by(|a, b| a.0.total_cmp(&b.0))?;
+    let root_in_bass = chord.is_root(bass);
+    if options.root_in_bass && !root_in_bass {
+        return None;
+    }
+
+    let (fingers, barre) = assign_fingers(tuning, frets)?;
+
+    let fretted = frets
+        .iter()
+        .enumerate()
+        .filter_map(|(string, fret)| Some((string as u8, (*fret)?)))
+        .filter(|(string, fret)| *fret != tuning.open_fret(*string))
+        .collect::<Vec<_>>();
+    let lowest = fretted.iter().map(|(_, f)| *f).min().unwrap_or(0);
+    let highest = fretted.iter().map(|(_, f)| *f).max().unwrap_or(0);
+    let open = frets.iter().flatten().count() - fretted.len();
+
+    // Muted strings between sounding strings are hard to damp
+    let first = frets.iter().position(Option::is_some)?;
+    let last = frets.iter().rposition(Option::is_some)?;
+    let muted_inside = frets[first..=last].iter().filter(|f| f.is_none()).count();
+    let muted_outside = frets.len() - (last - first + 1);
+
+    let mut cost = 0.0;
+    cost += (highest - lowest) as f32 * 1.0;
+    cost += lowest as f32 * 0.1;
+    cost += fretted.len() as f32 * 0.2;
+    cost += muted_inside as f32 * 3.0;
+    cost += muted_outside as f32 * 0.5;
+    cost -= open as f32 * 0.3;
+    if barre.is_some() {
+        cost += 1.5;
+    }
+    if !root_in_bass {
+        cost += 1.5;
+    }
+    if omits_fifth {
+        cost += 0.5;
+    }
+
+    Some(Voicing {
+        frets: frets.to_vec(),
+        fingers,
+        barre,
+        cost,
+    })
+}
+
+/// Assigns index to pinky by fret, using a barre with the index finger if there are more
+/// fretted strings than fingers. Returns `None` if the shape can't be fingered.
+fn assign_fingers(
+    tuning: &FrettedTuning,
+    frets: &[Option<u8>],
+) -> Option<(Vec<Option<Finger>>, Option<Barre>)> {
+    const FINGERS: [Finger; 4] = [Finger::Index, Finger::Middle, Finger::Ring, Finger::Pinky];
+
+    let mut fretted = frets
+        .iter()
+        .enumerate()
+        .filter_map(|(string, fret)| Some((string as u8, (*fret)?)))
+        .filter(|(string, fret)| *fret != tuning.open_fret(*string))
+        .collect::<Vec<_>>();
+    fretted.sort_by_key(|(string, fret)| (*fret, *string));
+
+    let mut fingers = vec![None; frets.len()];
+    if fretted.len() <= FINGERS.len() {
+        for ((string, _), finger) in fretted.iter().zip(FINGERS) {
+            fingers[*string as usize] = Some(finger);
+        }
+        return Some((fingers, None));
+    }
+
+    // Barre the lowest fret from its lowest string to the highest string
+    let fret = fretted[0].1;
+    let first_string = fretted
+        .iter()
+        .filter(|(_, f)| *f == fret)
+        .map(|(s, _)| *s)
+        .min()?;
+    let last_string = frets.len() as u8 - 1;
+    let covered = first_string..=last_string;
+    // Every string under the barre must be fretted at or above it
+    if frets[first_string as usize..]
+        .iter()
+        .any(|f| f.is_none_or(|f| f < fret))
+    {
+        return None;
+    }
+    let barre = Barre {
+        fret,
+        first_string,
+        last_string,
+        finger: Finger::Index,
+    };
+
+    let rest = fretted
+        .iter()
+        .filter(|(string, f)| !(*f == fret && covered.contains(string)))
+        .collect::<Vec<_>>();
+    if rest.len() > FINGERS.len() - 1 {
+        return None;
+    }
+    for string in covered.clone() {
+        if frets[string as usize] == Some(fret) {
+            fingers[string as usize] = Some(Finger::Index);
+        }
+    }
+    for ((string, _), finger) in rest.iter().zip(&FINGERS[1..]) {
+        fingers[*string as usize] = Some(*finger);
+    }
+    Some((fingers, Some(barre)))
+}
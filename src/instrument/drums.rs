@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use crate::note::rhythm::{Duration, Time};
+use crate::note::Note;
+use crate::score::Part;
+
+/// The midi channel General MIDI reserves for percussion (channel 10, counting from 1).
+pub const GM_PERCUSSION_CHANNEL: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Notehead {
+    Normal,
+    Cross,
+    CircledCross,
+    Diamond,
+    Triangle,
+}
+
+/// Drum charts write everything played with the hands as the upper voice (stems up) and
+/// everything played with the feet as the lower voice (stems down).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Limb {
+    Hands,
+    Feet,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrumPiece {
+    pub name: String,
+    /// The midi key that triggers this piece.
+    pub key: u8,
+    /// Position on the five line staff in steps (lines and spaces) above the bottom line.
+    pub staff_position: i8,
+    pub notehead: Notehead,
+    pub limb: Limb,
+}
+
+/// Maps midi keys to the pieces of a drum kit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrumKit {
+    pub pieces: BTreeMap<u8, DrumPiece>,
+}
+
+impl DrumKit {
+    pub fn general_midi() -> Self {
+        use Limb::*;
+        use Notehead::*;
+
+        #[rustfmt::skip]
+        let table = [
+            (35, "Acoustic Bass Drum", 0, Normal, Feet),
+            (36, "Bass Drum", 1, Normal, Feet),
+            (37, "Side Stick", 5, CircledCross, Hands),
+            (38, "Acoustic Snare", 5, Normal, Hands),
+            (39, "Hand Clap", 5, Cross, Hands),
+            (40, "Electric Snare", 5, Normal, Hands),
+            (41, "Low Floor Tom", 2, Normal, Hands),
+            (42, "Closed Hi-Hat", 9, Cross, Hands),
+            (43, "High Floor Tom", 3, Normal, Hands),
+            (44, "Pedal Hi-Hat", -1, Cross, Feet),
+            (45, "Low Tom", 4, Normal, Hands),
+            (46, "Open Hi-Hat", 9, CircledCross, Hands),
+            (47, "Low-Mid Tom", 6, Normal, Hands),
+            (48, "Hi-Mid Tom", 7, Normal, Hands),
+            (49, "Crash Cymbal 1", 10, Cross, Hands),
+            (50, "High Tom", 8, Normal, Hands),
+            (51, "Ride Cymbal 1", 8, Cross, Hands),
+            (52, "Chinese Cymbal", 11, CircledCross, Hands),
+            (53, "Ride Bell", 8, Diamond, Hands),
+            (54, "Tambourine", 7, Cross, Hands),
+            (55, "Splash Cymbal", 11, Cross, Hands),
+            (56, "Cowbell", 9, Triangle, Hands),
+            (57, "Crash Cymbal 2", 12, Cross, Hands),
+            (58, "Vibraslap", 6, Cross, Hands),
+            (59, "Ride Cymbal 2", 7, Cross, Hands),
+            (60, "Hi Bongo", 7, Normal, Hands),
+            (61, "Low Bongo", 6, Normal, Hands),
+            (62, "Mute Hi Conga", 5, Cross, Hands),
+            (63, "Open Hi Conga", 5, Normal, Hands),
+            (64, "Low Conga", 4, Normal, Hands),
+            (65, "High Timbale", 7, Normal, Hands),
+            (66, "Low Timbale", 6, Normal, Hands),
+            (67, "High Agogo", 9, Triangle, Hands),
+            (68, "Low Agogo", 8, Triangle, Hands),
+            (69, "Cabasa", 8, Cross, Hands),
+            (70, "Maracas", 7, Cross, Hands),
+            (71, "Short Whistle", 10, Normal, Hands),
+            (72, "Long Whistle", 10, Normal, Hands),
+            (73, "Short Guiro", 6, Cross, Hands),
+            (74, "Long Guiro", 6, Cross, Hands),
+            (75, "Claves", 9, Normal, Hands),
+            (76, "Hi Wood Block", 8, Triangle, Hands),
+            (77, "Low Wood Block", 7, Triangle, Hands),
+            (78, "Mute Cuica", 6, Cross, Hands),
+            (79, "Open Cuica", 6, Normal, Hands),
+            (80, "Mute Triangle", 10, Triangle, Hands),
+            (81, "Open Triangle", 10, Triangle, Hands),
+        ];
+
+        DrumKit {
+            pieces: table
+                .into_iter()
+                .map(|(key, name, staff_position, notehead, limb)| {
+                    (key, DrumPiece {
+                        name: name.to_string(),
+                        key,
+                        staff_position,
+                        notehead,
+                        limb,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn piece(&self, key: u8) -> Option<&DrumPiece> { self.pieces.get(&key) }
+    pub fn piece_of(&self, note: &Note) -> Option<&DrumPiece> {
+        u8::try_from(note.pitch.to_midi())
+            .ok()
+            .and_then(|key| self.piece(key))
+    }
+    pub fn by_name(&self, name: &str) -> Option<&DrumPiece> {
+        self.pieces
+            .values()
+            .find(|piece| piece.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The pieces used by `notes`, from the top of the staff down. Useful as the rows of a
+    /// drum grid.
+    pub fn used_pieces<'a>(&'a self, notes: &[Note]) -> Vec<&'a DrumPiece> {
+        let mut pieces = notes
+            .iter()
+            .filter_map(|note| self.piece_of(note))
+            .collect::<Vec<_>>();
+        pieces.sort_by_key(|piece| (std::cmp::Reverse(piece.staff_position), piece.key));
+        pieces.dedup_by_key(|piece| piece.key);
+        pieces
+    }
+}
+
+/// A note of a drum chart, ready to be placed on a staff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrumNote {
+    pub time: Time,
+    pub duration: Duration,
+    /// Index into `Part::notes`.
+    pub note: usize,
+    pub staff_position: i8,
+    pub notehead: Notehead,
+}
+
+/// A drum part split into the two voices of a drum chart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrumChart {
+    /// Stems up.
+    pub hands:    Vec<DrumNote>,
+    /// Stems down.
+    pub feet:     Vec<DrumNote>,
+    /// Notes whose key isn't part of the kit.
+    pub unmapped: Vec<usize>,
+}
+
+impl DrumChart {
+    pub fn new(part: &Part, kit: &DrumKit) -> Self {
+        let mut chart = DrumChart::default();
+        for (i, note) in part.notes.iter().enumerate() {
+            let Some(piece) = kit.piece_of(note)
+            else {
+                chart.unmapped.push(i);
+                continue;
+            };
+            let drum_note = DrumNote {
+                time: note.time,
+                duration: note.duration,
+                note: i,
+                staff_position: piece.staff_position,
+                notehead: piece.notehead,
+            };
+            match piece.limb {
+                Limb::Hands => chart.hands.push(drum_note),
+                Limb::Feet => chart.feet.push(drum_note),
+            }
+        }
+        chart.hands.sort_by_key(|n| (n.time, n.staff_position));
+        chart.feet.sort_by_key(|n| (n.time, n.staff_position));
+        chart
+    }
+
+    /// Splits the drum part into a hands and a feet part, e.g. for exporting them as two voices.
+    pub fn to_parts(&self, part: &Part) -> (Part, Part) {
+        let voice = |notes: &[DrumNote]| Part {
+            notes: notes.iter().map(|n| part.notes[n.note].clone()).collect(),
+            ..part.clone()
+        };
+        (voice(&self.hands), voice(&self.feet))
+    }
+}
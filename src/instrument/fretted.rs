@@ -0,0 +1,348 @@
+use crate::note::harmony::{Chroma, Interval, Pitch};
+use crate::note::Note;
+
+/// A capo clamped at `fret`. Partial capos only cover some of the strings.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Capo {
+    pub fret:    u8,
+    /// Indices of the covered strings. `None` covers all strings.
+    pub strings: Option<Vec<u8>>,
+}
+impl Capo {
+    pub fn full(fret: u8) -> Capo {
+        Capo {
+            fret,
+            strings: None,
+        }
+    }
+    pub fn partial(fret: u8, strings: impl Into<Vec<u8>>) -> Capo {
+        Capo {
+            fret,
+            strings: Some(strings.into()),
+        }
+    }
+
+    pub fn covers(&self, string: u8) -> bool {
+        self.strings
+            .as_ref()
+            .is_none_or(|strings| strings.contains(&string))
+    }
+}
+
+/// The open strings of a fretted instrument, in the order of the tab lines from bottom to top.
+/// For most instruments that is from the lowest to the highest string, re-entrant tunings
+/// (ukulele, banjo) are ordered by their position on the neck instead.
+///
+/// Each entry of `strings` is a course: strings that are played together as one, like on a
+/// mandolin or 12-string guitar. The additional strings are listed in `courses`.
+///
+/// Fret numbers are always counted from the nut, so with a capo at the 2nd fret
+/// the "open" string is fret 2 and fret 0 and 1 can't be played.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct FrettedTuning {
+    pub strings: Vec<Pitch>,
+    pub frets: usize,
+    /// Per-string fret limits, indexed like `strings`. Missing entries use `frets`.
+    pub string_frets: Vec<Option<usize>>,
+    pub capos: Vec<Capo>,
+    /// Additional strings of each course, relative to the main string. Indexed like `strings`.
+    pub courses: Vec<Vec<Interval>>,
+    /// The fret at which a string leaves the neck, e.g. 5 for the short fifth string of a banjo.
+    /// Indexed like `strings`, missing entries are 0.
+    pub nut_frets: Vec<u8>,
+}
+impl Default for FrettedTuning {
+    fn default() -> FrettedTuning { FrettedTuning::standard() }
+}
+impl FrettedTuning {
+    pub fn from_intervals(lowest: Pitch, intervals: &[Interval]) -> FrettedTuning {
+        let mut strings = vec![lowest];
+        for interval in intervals {
+            strings.push(*strings.last().unwrap() + *interval);
+        }
+        FrettedTuning::from_pitches(strings)
+    }
+    pub fn from_pitches(strings: Vec<Pitch>) -> FrettedTuning {
+        FrettedTuning {
+            strings,
+            frets: 24,
+            string_frets: Vec::new(),
+            capos: Vec::new(),
+            courses: Vec::new(),
+            nut_frets: Vec::new(),
+        }
+    }
+    fn from_chromas(strings: &[(Chroma, i32)]) -> FrettedTuning {
+        FrettedTuning::from_pitches(
+            strings
+                .iter()
+                .map(|&(chroma, octave)| Pitch::from_chroma_octave(chroma, octave))
+                .collect(),
+        )
+    }
+
+    pub fn with_frets(mut self, frets: usize) -> Self {
+        self.frets = frets;
+        self
+    }
+    pub fn with_capo(mut self, capo: Capo) -> Self {
+        self.capos.push(capo);
+        self
+    }
+    pub fn with_string_frets(mut self, string: u8, frets: usize) -> Self {
+        let string = string as usize;
+        if self.string_frets.len() <= string {
+            self.string_frets.resize(string + 1, None);
+        }
+        self.string_frets[string] = Some(frets);
+        self
+    }
+    /// Turns every string into a course with the same additional strings.
+    pub fn with_courses(mut self, course: &[Interval]) -> Self {
+        self.courses = vec![course.to_vec(); self.strings.len()];
+        self
+    }
+    pub fn with_nut_fret(mut self, string: u8, fret: u8) -> Self {
+        let string = string as usize;
+        if self.nut_frets.len() <= string {
+            self.nut_frets.resize(string + 1, 0);
+        }
+        self.nut_frets[string] = fret;
+        self
+    }
+
+    /// The fret the capo is clamped at on `string`, 0 if there is no capo.
+    pub fn capo_fret(&self, string: u8) -> u8 {
+        self.capos
+            .iter()
+            .filter(|capo| capo.covers(string))
+            .map(|capo| capo.fret)
+            .max()
+            .unwrap_or(0)
+    }
+    pub fn frets_on(&self, string: u8) -> usize {
+        self.string_frets
+            .get(string as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(self.frets)
+    }
+    pub fn nut_fret(&self, string: u8) -> u8 {
+        self.nut_frets.get(string as usize).copied().unwrap_or(0)
+    }
+    /// The fret that is played when no fret is pressed, taking capos into account.
+    pub fn open_fret(&self, string: u8) -> u8 {
+        let capo = self.capo_fret(string);
+        if capo > self.nut_fret(string) {
+            capo
+        }
+        else {
+            0
+        }
+    }
+    /// The pitch of `string` when no fret is pressed, taking capos into account.
+    pub fn open_pitch(&self, string: u8) -> Option<Pitch> {
+        self.pitch_at(string, self.open_fret(string))
+    }
+
+    /// The pitch of `string` pressed at `fret`, or `None` if that position can't be played.
+    pub fn pitch_at(&self, string: u8, fret: u8) -> Option<Pitch> {
+        let open = *self.strings.get(string as usize)?;
+        let nut = self.nut_fret(string);
+        let capo = self.capo_fret(string);
+        if fret == 0 && capo <= nut {
+            return Some(open);
+        }
+        if fret <= nut || fret < capo || fret as usize > self.frets_on(string) {
+            return None;
+        }
+        Some(open + Interval::from_halfsteps((fret - nut) as f32))
+    }
+    /// All pitches that sound when the course of `string` is pressed at `fret`.
+    pub fn course_pitches_at(&self, string: u8, fret: u8) -> Vec<Pitch> {
+        let Some(pitch) = self.pitch_at(string, fret)
+        else {
+            return Vec::new();
+        };
+        let extra = self.courses.get(string as usize).into_iter().flatten();
+        std::iter::once(pitch)
+            .chain(extra.map(|interval| pitch + *interval))
+            .collect()
+    }
+    /// The fret that produces `pitch` on `string`, or `None` if it can't be played there.
+    pub fn fret_for_pitch(&self, string: u8, pitch: Pitch) -> Option<u8> {
+        let open = *self.strings.get(string as usize)?;
+        let nut = self.nut_fret(string);
+        let capo = self.capo_fret(string);
+        let halfsteps = (pitch - open).halfsteps().round();
+        if halfsteps < 0.0 {
+            return None;
+        }
+        if halfsteps == 0.0 && capo <= nut {
+            return Some(0);
+        }
+        let fret = halfsteps + nut as f32;
+        if fret <= nut as f32 || fret < capo as f32 || fret > self.frets_on(string) as f32 {
+            return None;
+        }
+        Some(fret as u8)
+    }
+    /// The fret of a note that already has a string assigned.
+    pub fn fret_for(&self, note: &Note) -> Option<u8> {
+        self.fret_for_pitch(note.string?, note.pitch)
+    }
+    /// All `(string, fret)` positions that produce `pitch`, in string order.
+    pub fn positions(&self, pitch: Pitch) -> impl Iterator<Item = (u8, u8)> + '_ {
+        (0..self.strings.len() as u8)
+            .filter_map(move |string| Some((string, self.fret_for_pitch(string, pitch)?)))
+    }
+}
+
+// Named guitar tunings
+impl FrettedTuning {
+    pub fn standard() -> FrettedTuning {
+        FrettedTuning::from_intervals(Pitch::from_chroma_octave(Chroma::E, 2), &[
+            Interval::FOURTH,
+            Interval::FOURTH,
+            Interval::FOURTH,
+            Interval::MAJOR_THIRD,
+            Interval::FOURTH,
+        ])
+    }
+    pub fn drop_d() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(D, 2), (A, 2), (D, 3), (G, 3), (B, 3), (E, 4)])
+    }
+    pub fn dadgad() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(D, 2), (A, 2), (D, 3), (G, 3), (A, 3), (D, 4)])
+    }
+    pub fn open_g() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(D, 2), (G, 2), (D, 3), (G, 3), (B, 3), (D, 4)])
+    }
+    pub fn open_d() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(D, 2), (A, 2), (D, 3), (FSharp, 3), (A, 3), (D, 4)])
+    }
+    pub fn open_e() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(E, 2), (B, 2), (E, 3), (GSharp, 3), (B, 3), (E, 4)])
+    }
+    pub fn seven_string() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(B, 1), (E, 2), (A, 2), (D, 3), (G, 3), (B, 3), (E, 4)])
+    }
+    pub fn eight_string() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[
+            (FSharp, 1),
+            (B, 1),
+            (E, 2),
+            (A, 2),
+            (D, 3),
+            (G, 3),
+            (B, 3),
+            (E, 4),
+        ])
+    }
+    /// Standard tuning a fourth down.
+    pub fn baritone() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(B, 1), (E, 2), (A, 2), (D, 3), (FSharp, 3), (B, 3)])
+    }
+    /// Standard tuning with the four lower courses doubled an octave up and the two higher
+    /// courses doubled in unison.
+    pub fn twelve_string() -> FrettedTuning {
+        let mut tuning = FrettedTuning::standard();
+        tuning.courses = vec![
+            vec![Interval::OCTAVE],
+            vec![Interval::OCTAVE],
+            vec![Interval::OCTAVE],
+            vec![Interval::OCTAVE],
+            vec![Interval::UNISON],
+            vec![Interval::UNISON],
+        ];
+        tuning
+    }
+}
+
+// Other fretted instruments
+impl FrettedTuning {
+    pub fn bass() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(E, 1), (A, 1), (D, 2), (G, 2)]).with_frets(20)
+    }
+    pub fn bass_five_string() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(B, 0), (E, 1), (A, 1), (D, 2), (G, 2)])
+    }
+    pub fn bass_six_string() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(B, 0), (E, 1), (A, 1), (D, 2), (G, 2), (C, 3)])
+    }
+    /// Re-entrant: the G string is tuned above the C string.
+    pub fn ukulele() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(G, 4), (C, 4), (E, 4), (A, 4)]).with_frets(15)
+    }
+    pub fn ukulele_low_g() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(G, 3), (C, 4), (E, 4), (A, 4)]).with_frets(15)
+    }
+    pub fn mandolin() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(G, 3), (D, 4), (A, 4), (E, 5)])
+            .with_frets(20)
+            .with_courses(&[Interval::UNISON])
+    }
+    /// Open G with the short fifth string, which starts at the 5th fret.
+    pub fn banjo() -> FrettedTuning {
+        use Chroma::*;
+        FrettedTuning::from_chromas(&[(G, 4), (D, 3), (G, 3), (B, 3), (D, 4)])
+            .with_frets(22)
+            .with_nut_fret(0, 5)
+    }
+
+    pub fn catalogue() -> Vec<(&'static str, FrettedTuning)> {
+        vec![
+            ("Standard", FrettedTuning::standard()),
+            ("Drop D", FrettedTuning::drop_d()),
+            ("DADGAD", FrettedTuning::dadgad()),
+            ("Open G", FrettedTuning::open_g()),
+            ("Open D", FrettedTuning::open_d()),
+            ("Open E", FrettedTuning::open_e()),
+            ("7-String", FrettedTuning::seven_string()),
+            ("8-String", FrettedTuning::eight_string()),
+            ("Baritone", FrettedTuning::baritone()),
+            ("12-String", FrettedTuning::twelve_string()),
+            ("Bass", FrettedTuning::bass()),
+            ("5-String Bass", FrettedTuning::bass_five_string()),
+            ("6-String Bass", FrettedTuning::bass_six_string()),
+            ("Ukulele", FrettedTuning::ukulele()),
+            ("Ukulele (Low G)", FrettedTuning::ukulele_low_g()),
+            ("Mandolin", FrettedTuning::mandolin()),
+            ("Banjo", FrettedTuning::banjo()),
+        ]
+    }
+    pub fn by_name(name: &str) -> Option<FrettedTuning> {
+        FrettedTuning::catalogue()
+            .into_iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, tuning)| tuning)
+    }
+}
+
+pub fn guess_fingerings(tuning: &FrettedTuning, notes: &mut [Note]) {
+    // TODO: Use an actual heuristic
+    for note in notes {
+        if note.string.is_some() {
+            continue;
+        }
+
+        note.string = tuning
+            .positions(note.pitch)
+            .map(|(string, _)| string)
+            .next();
+    }
+}
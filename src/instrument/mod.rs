@@ -1 +1,481 @@
+pub mod chord_shapes;
+pub mod drums;
+pub mod fretted;
 pub mod guitar;
+pub mod piano;
+
+use crate::note::harmony::{Chroma, Interval, KeySignature, Pitch};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Clef {
+    Treble,
+    /// Treble clef sounding an octave lower, e.g. for guitar.
+    TrebleOctaveDown,
+    Bass,
+    Alto,
+    Tenor,
+    /// Treble and bass staff, e.g. for piano.
+    Grand,
+    Percussion,
+    Tab,
+}
+
+/// Whether notes are shown as they sound or as the player reads them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PitchDisplay {
+    #[default]
+    Concert,
+    Written,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RangeStatus {
+    Comfortable,
+    /// Playable, but at the edge of the range.
+    Extreme,
+    OutOfRange,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instrument {
+    pub name: String,
+    /// General MIDI program number (0-127).
+    pub program: u8,
+    /// Lowest and highest sounding pitch, inclusive.
+    pub range: (Pitch, Pitch),
+    /// Lowest and highest sounding pitch that can be played without effort, inclusive.
+    pub comfortable_range: (Pitch, Pitch),
+    pub clef: Clef,
+    /// Sounding pitch minus written pitch, e.g. a major second down for a Bb clarinet.
+    pub transposition: Interval,
+    pub channel: u8,
+}
+
+impl Instrument {
+    pub fn new(name: impl Into<String>, program: u8, range: (Pitch, Pitch), clef: Clef) -> Self {
+        Instrument {
+            name: name.into(),
+            program,
+            range,
+            comfortable_range: range,
+            clef,
+            transposition: Interval::ZERO,
+            channel: 0,
+        }
+    }
+    pub fn with_comfortable_range(mut self, lowest: Pitch, highest: Pitch) -> Self {
+        self.comfortable_range = (lowest, highest);
+        self
+    }
+    pub fn with_transposition(mut self, transposition: Interval) -> Self {
+        self.transposition = transposition;
+        self
+    }
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn written_pitch(&self, sounding: Pitch) -> Pitch { sounding - self.transposition }
+    pub fn sounding_pitch(&self, written: Pitch) -> Pitch { written + self.transposition }
+    /// Converts a sounding pitch for display.
+    pub fn display_pitch(&self, sounding: Pitch, display: PitchDisplay) -> Pitch {
+        match display {
+            PitchDisplay::Concert => sounding,
+            PitchDisplay::Written => self.written_pitch(sounding),
+        }
+    }
+    /// Converts a concert key signature for display.
+    pub fn display_key_signature(
+        &self,
+        concert: &KeySignature,
+        display: PitchDisplay,
+    ) -> KeySignature {
+        match display {
+            PitchDisplay::Concert => concert.clone(),
+            PitchDisplay::Written => concert.transposed(-self.transposition),
+        }
+    }
+
+    /// Moves `sounding` by octaves until it is inside the range. Pitches stay where they are
+    /// if the range is smaller than an octave.
+    pub fn fold_into_range(&self, sounding: Pitch) -> Pitch {
+        let (lowest, highest) = self.range;
+        let mut pitch = sounding;
+        while pitch > highest && pitch - Interval::OCTAVE >= lowest {
+            pitch -= Interval::OCTAVE;
+        }
+        while pitch < lowest && pitch + Interval::OCTAVE <= highest {
+            pitch += Interval::OCTAVE;
+        }
+        pitch
+    }
+    pub fn range_status(&self, sounding: Pitch) -> RangeStatus {
+        let contains =
+            |(lowest, highest): (Pitch, Pitch)| lowest <= sounding && sounding <= highest;
+        if contains(self.comfortable_range) {
+            RangeStatus::Comfortable
+        }
+        else if contains(self.range) {
+            RangeStatus::Extreme
+        }
+        else {
+            RangeStatus::OutOfRange
+        }
+    }
+
+    /// The instrument of a General MIDI program change. Programs without an entry in the
+    /// catalogue get the full midi range.
+    pub fn from_gm_program(program: u8) -> Self {
+        Instrument::catalogue()
+            .into_iter()
+            .find(|i| i.program == program)
+            .unwrap_or_else(|| {
+                let name = GM_PROGRAM_NAMES
+                    .get(program as usize)
+                    .copied()
+                    .unwrap_or("Unknown");
+                Instrument::new(
+                    name,
+                    program,
+                    (Pitch::from_midi(0), Pitch::from_midi(127)),
+                    Clef::Treble,
+                )
+            })
+    }
+}
+
+// Catalogue
+impl Instrument {
+    pub fn piano() -> Self {
+        Instrument::new(
+            "Piano",
+            0,
+            (pitch(Chroma::A, 0), pitch(Chroma::C, 8)),
+            Clef::Grand,
+        )
+    }
+    pub fn acoustic_guitar() -> Self {
+        Instrument::new(
+            "Acoustic Guitar",
+            24,
+            (pitch(Chroma::E, 2), pitch(Chroma::B, 5)),
+            Clef::TrebleOctaveDown,
+        )
+        .with_comfortable_range(pitch(Chroma::E, 2), pitch(Chroma::E, 5))
+        .with_transposition(-Interval::OCTAVE)
+    }
+    pub fn electric_bass() -> Self {
+        Instrument::new(
+            "Electric Bass",
+            33,
+            (pitch(Chroma::E, 1), pitch(Chroma::G, 4)),
+            Clef::Bass,
+        )
+        .with_comfortable_range(pitch(Chroma::E, 1), pitch(Chroma::C, 4))
+        .with_transposition(-Interval::OCTAVE)
+    }
+    pub fn violin() -> Self {
+        Instrument::new(
+            "Violin",
+            40,
+            (pitch(Chroma::G, 3), pitch(Chroma::A, 7)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::G, 3), pitch(Chroma::E, 6))
+    }
+    pub fn viola() -> Self {
+        Instrument::new(
+            "Viola",
+            41,
+            (pitch(Chroma::C, 3), pitch(Chroma::E, 6)),
+            Clef::Alto,
+        )
+        .with_comfortable_range(pitch(Chroma::C, 3), pitch(Chroma::A, 5))
+    }
+    pub fn cello() -> Self {
+        Instrument::new(
+            "Cello",
+            42,
+            (pitch(Chroma::C, 2), pitch(Chroma::C, 6)),
+            Clef::Bass,
+        )
+        .with_comfortable_range(pitch(Chroma::C, 2), pitch(Chroma::A, 4))
+    }
+    pub fn contrabass() -> Self {
+        Instrument::new(
+            "Contrabass",
+            43,
+            (pitch(Chroma::E, 1), pitch(Chroma::G, 4)),
+            Clef::Bass,
+        )
+        .with_comfortable_range(pitch(Chroma::E, 1), pitch(Chroma::D, 3))
+        .with_transposition(-Interval::OCTAVE)
+    }
+    pub fn flute() -> Self {
+        Instrument::new(
+            "Flute",
+            73,
+            (pitch(Chroma::C, 4), pitch(Chroma::D, 7)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::D, 4), pitch(Chroma::A, 6))
+    }
+    pub fn oboe() -> Self {
+        Instrument::new(
+            "Oboe",
+            68,
+            (pitch(Chroma::BFlat, 3), pitch(Chroma::A, 6)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::C, 4), pitch(Chroma::E, 6))
+    }
+    pub fn clarinet() -> Self {
+        Instrument::new(
+            "Clarinet in Bb",
+            71,
+            (pitch(Chroma::D, 3), pitch(Chroma::BFlat, 6)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::E, 3), pitch(Chroma::G, 6))
+        .with_transposition(-Interval::MAJOR_SECOND)
+    }
+    pub fn bassoon() -> Self {
+        Instrument::new(
+            "Bassoon",
+            70,
+            (pitch(Chroma::BFlat, 1), pitch(Chroma::E, 5)),
+            Clef::Bass,
+        )
+        .with_comfortable_range(pitch(Chroma::C, 2), pitch(Chroma::C, 5))
+    }
+    pub fn alto_sax() -> Self {
+        Instrument::new(
+            "Alto Saxophone",
+            65,
+            (pitch(Chroma::DFlat, 3), pitch(Chroma::AFlat, 5)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::F, 3), pitch(Chroma::E, 5))
+        .with_transposition(-Interval::MAJOR_SIXTH)
+    }
+    pub fn tenor_sax() -> Self {
+        Instrument::new(
+            "Tenor Saxophone",
+            66,
+            (pitch(Chroma::AFlat, 2), pitch(Chroma::E, 5)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::C, 3), pitch(Chroma::A, 4))
+        .with_transposition(-Interval::MAJOR_NINTH)
+    }
+    pub fn trumpet() -> Self {
+        Instrument::new(
+            "Trumpet in Bb",
+            56,
+            (pitch(Chroma::E, 3), pitch(Chroma::BFlat, 5)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::G, 3), pitch(Chroma::F, 5))
+        .with_transposition(-Interval::MAJOR_SECOND)
+    }
+    pub fn french_horn() -> Self {
+        Instrument::new(
+            "Horn in F",
+            60,
+            (pitch(Chroma::B, 1), pitch(Chroma::F, 5)),
+            Clef::Treble,
+        )
+        .with_comfortable_range(pitch(Chroma::C, 3), pitch(Chroma::C, 5))
+        .with_transposition(-Interval::FIFTH)
+    }
+    pub fn trombone() -> Self {
+        Instrument::new(
+            "Trombone",
+            57,
+            (pitch(Chroma::E, 2), pitch(Chroma::F, 5)),
+            Clef::Bass,
+        )
+        .with_comfortable_range(pitch(Chroma::A, 2), pitch(Chroma::BFlat, 4))
+    }
+    pub fn tuba() -> Self {
+        Instrument::new(
+            "Tuba",
+            58,
+            (pitch(Chroma::D, 1), pitch(Chroma::F, 4)),
+            Clef::Bass,
+        )
+        .with_comfortable_range(pitch(Chroma::F, 1), pitch(Chroma::F, 3))
+    }
+
+    /// A General MIDI drum kit on the percussion channel. See `drums::DrumKit` for the mapping
+    /// of keys to drums.
+    pub fn drum_kit() -> Self {
+        Instrument::new(
+            "Drum Kit",
+            0,
+            (Pitch::from_midi(35), Pitch::from_midi(81)),
+            Clef::Percussion,
+        )
+        .with_channel(drums::GM_PERCUSSION_CHANNEL)
+    }
+    pub fn is_percussion(&self) -> bool { self.clef == Clef::Percussion }
+
+    pub fn catalogue() -> Vec<Instrument> {
+        vec![
+            Instrument::piano(),
+            Instrument::acoustic_guitar(),
+            Instrument::electric_bass(),
+            Instrument::violin(),
+            Instrument::viola(),
+            Instrument::cello(),
+            Instrument::contrabass(),
+            Instrument::flute(),
+            Instrument::oboe(),
+            Instrument::clarinet(),
+            Instrument::bassoon(),
+            Instrument::alto_sax(),
+            Instrument::tenor_sax(),
+            Instrument::trumpet(),
+            Instrument::french_horn(),
+            Instrument::trombone(),
+            Instrument::tuba(),
+        ]
+    }
+}
+
+fn pitch(chroma: Chroma, octave: i32) -> Pitch { Pitch::from_chroma_octave(chroma, octave) }
+
+pub const GM_PROGRAM_NAMES: [&str; 128] = [
+    "Acoustic Grand Piano",
+    "Bright Acoustic Piano",
+    "Electric Grand Piano",
+    "Honky-tonk Piano",
+    "Electric Piano 1",
+    "Electric Piano 2",
+    "Harpsichord",
+    "Clavinet",
+    "Celesta",
+    "Glockenspiel",
+    "Music Box",
+    "Vibraphone",
+    "Marimba",
+    "Xylophone",
+    "Tubular Bells",
+    "Dulcimer",
+    "Drawbar Organ",
+    "Percussive Organ",
+    "Rock Organ",
+    "Church Organ",
+    "Reed Organ",
+    "Accordion",
+    "Harmonica",
+    "Tango Accordion",
+    "Acoustic Guitar (nylon)",
+    "Acoustic Guitar (steel)",
+    "Electric Guitar (jazz)",
+    "Electric Guitar (clean)",
+    "Electric Guitar (muted)",
+    "Overdriven Guitar",
+    "Distortion Guitar",
+    "Guitar Harmonics",
+    "Acoustic Bass",
+    "Electric Bass (finger)",
+    "Electric Bass (pick)",
+    "Fretless Bass",
+    "Slap Bass 1",
+    "Slap Bass 2",
+    "Synth Bass 1",
+    "Synth Bass 2",
+    "Violin",
+    "Viola",
+    "Cello",
+    "Contrabass",
+    "Tremolo Strings",
+    "Pizzicato Strings",
+    "Orchestral Harp",
+    "Timpani",
+    "String Ensemble 1",
+    "String Ensemble 2",
+    "Synth Strings 1",
+    "Synth Strings 2",
+    "Choir Aahs",
+    "Voice Oohs",
+    "Synth Voice",
+    "Orchestra Hit",
+    "Trumpet",
+    "Trombone",
+    "Tuba",
+    "Muted Trumpet",
+    "French Horn",
+    "Brass Section",
+    "Synth Brass 1",
+    "Synth Brass 2",
+    "Soprano Sax",
+    "Alto Sax",
+    "Tenor Sax",
+    "Baritone Sax",
+    "Oboe",
+    "English Horn",
+    "Bassoon",
+    "Clarinet",
+    "Piccolo",
+    "Flute",
+    "Recorder",
+    "Pan Flute",
+    "Blown Bottle",
+    "Shakuhachi",
+    "Whistle",
+    "Ocarina",
+    "Lead 1 (square)",
+    "Lead 2 (sawtooth)",
+    "Lead 3 (calliope)",
+    "Lead 4 (chiff)",
+    "Lead 5 (charang)",
+    "Lead 6 (voice)",
+    "Lead 7 (fifths)",
+    "Lead 8 (bass + lead)",
+    "Pad 1 (new age)",
+    "Pad 2 (warm)",
+    "Pad 3 (polysynth)",
+    "Pad 4 (choir)",
+    "Pad 5 (bowed)",
+    "Pad 6 (metallic)",
+    "Pad 7 (halo)",
+    "Pad 8 (sweep)",
+    "FX 1 (rain)",
+    "FX 2 (soundtrack)",
+    "FX 3 (crystal)",
+    "FX 4 (atmosphere)",
+    "FX 5 (brightness)",
+    "FX 6 (goblins)",
+    "FX 7 (echoes)",
+    "FX 8 (sci-fi)",
+    "Sitar",
+    "Banjo",
+    "Shamisen",
+    "Koto",
+    "Kalimba",
+    "Bagpipe",
+    "Fiddle",
+    "Shanai",
+    "Tinkle Bell",
+    "Agogo",
+    "Steel Drums",
+    "Woodblock",
+    "Taiko Drum",
+    "Melodic Tom",
+    "Synth Drum",
+    "Reverse Cymbal",
+    "Guitar Fret Noise",
+    "Breath Noise",
+    "Seashore",
+    "Bird Tweet",
+    "Telephone Ring",
+    "Helicopter",
+    "Applause",
+    "Gunshot",
+];
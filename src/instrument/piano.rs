@@ -0,0 +1,359 @@
+use crate::note::articulation::{Finger, Hand};
+use crate::note::harmony::{Chroma, Interval, Pitch};
+use crate::note::Note;
+use crate::score::Part;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PianoOptions {
+    /// Notes below this pitch prefer the left hand, notes above it the right hand.
+    pub split:    Pitch,
+    /// Widest chord a single hand can play.
+    pub max_span: Interval,
+}
+impl Default for PianoOptions {
+    fn default() -> Self {
+        PianoOptions {
+            split:    Pitch::from_chroma_octave(Chroma::C, 4),
+            max_span: Interval::MAJOR_TENTH,
+        }
+    }
+}
+
+/// Decides which hand plays each note, indexed like `notes`.
+///
+/// Notes starting at the same time are split into a lower part for the left and an upper part
+/// for the right hand. The split is chosen so that both hands move as little as possible, which
+/// lets hands cross when the music asks for it. Hands already set in `Note::finger` are kept.
+pub fn split_hands(notes: &[Note], options: &PianoOptions) -> Vec<Hand> {
+    let mut hands = vec![Hand::Right; notes.len()];
+    let mut left_position = options.split - Interval::OCTAVE;
+    let mut right_position = options.split + Interval::OCTAVE;
+
+    for chord in chords(notes) {
+        let pitches = chord.iter().map(|i| notes[*i].pitch).collect::<Vec<_>>();
+        let fixed = chord
+            .iter()
+            .map(|i| notes[*i].finger.map(|(_, hand)| hand))
+            .collect::<Vec<_>>();
+
+        // Try every split point of the (pitch sorted) chord
+        let cost = |k: usize| {
+            split_cost(&pitches[..k], left_position, options, Hand::Left)
+                + split_cost(&pitches[k..], right_position, options, Hand::Right)
+        };
+        let cheapest = |a: &usize, b: &usize| cost(*a).total_cmp(&cost(*b));
+        let best = (0..=chord.len())
+            .filter(|k| {
+                fixed[..*k].iter().all(|h| *h != Some(Hand::Right))
+                    && fixed[*k..].iter().all(|h| *h != Some(Hand::Left))
+            })
+            .min_by(cheapest)
+            // Fixed hands cross, split the rest around them
+            .unwrap_or_else(|| (0..=chord.len()).min_by(cheapest).unwrap_or(0));
+
+        for (j, i) in chord.iter().enumerate() {
+            hands[*i] = fixed[j].unwrap_or(if j < best { Hand::Left } else { Hand::Right });
+        }
+        if best > 0 {
+            left_position = center(&pitches[..best]);
+        }
+        if best < chord.len() {
+            right_position = center(&pitches[best..]);
+        }
+    }
+
+    hands
+}
+
+fn split_cost(pitches: &[Pitch], position: Pitch, options: &PianoOptions, hand: Hand) -> f32 {
+    let (Some(lowest), Some(highest)) = (pitches.first(), pitches.last())
+    else {
+        return 0.0;
+    };
+    if pitches.len() > 5 || *highest - *lowest > options.max_span {
+        return 1000.0;
+    }
+    let movement = (center(pitches) - position).halfsteps().abs();
+    let wrong_side = pitches
+        .iter()
+        .map(|p| match hand {
+            Hand::Left => (*p - options.split).halfsteps().max(0.0),
+            Hand::Right => (options.split - *p).halfsteps().max(0.0),
+        })
+        .sum::<f32>();
+    movement + wrong_side * 0.5
+}
+
+fn center(pitches: &[Pitch]) -> Pitch {
+    Pitch(pitches.iter().map(|p| p.0).sum::<f32>() / pitches.len() as f32)
+}
+
+/// Indices of the notes grouped by start time, each group sorted by pitch.
+fn chords(notes: &[Note]) -> Vec<Vec<usize>> {
+    let mut order = (0..notes.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| {
+        let (a, b) = (&notes[*a], &notes[*b]);
+        a.time.cmp(&b.time).then(a.pitch.0.total_cmp(&b.pitch.0))
+    });
+    order
+        .chunk_by(|a, b| notes[*a].time == notes[*b].time)
+        .map(<[usize]>::to_vec)
+        .collect()
+}
+
+/// Splits the notes into a left and right hand part.
+pub fn split_part(part: &Part, options: &PianoOptions) -> (Part, Part) {
+    let hands = split_hands(&part.notes, options);
+    let mut left = Part {
+        notes: Vec::new(),
+        ..part.clone()
+    };
+    let mut right = left.clone();
+    for (note, hand) in part.notes.iter().zip(hands) {
+        match hand {
+            Hand::Left => left.notes.push(note.clone()),
+            Hand::Right => right.notes.push(note.clone()),
+        }
+    }
+    (left, right)
+}
+
+/// Splits the notes into hands and assigns fingers, writing the result to `Note::finger`.
+/// Notes that already have a finger keep it.
+pub fn guess_fingerings(notes: &mut [Note], options: &PianoOptions) {
+    let hands = split_hands(notes, options);
+    for hand in [Hand::Left, Hand::Right] {
+        let indices = (0..notes.len())
+            .filter(|i| hands[*i] == hand)
+            .collect::<Vec<_>>();
+        let hand_notes = indices
+            .iter()
+            .map(|i| notes[*i].clone())
+            .collect::<Vec<_>>();
+        let fingers = finger_hand(&hand_notes, hand);
+        for (i, finger) in indices.into_iter().zip(fingers) {
+            if notes[i].finger.is_none() {
+                notes[i].finger = finger.map(|finger| (finger, hand));
+            }
+        }
+    }
+}
+
+const FINGERS: [Finger; 5] = [
+    Finger::Thumb,
+    Finger::Index,
+    Finger::Middle,
+    Finger::Ring,
+    Finger::Pinky,
+];
+
+struct Candidate {
+    /// Indices into `FINGERS`, one per chord note.
+    fingers: Vec<usize>,
+    cost:    f32,
+    from:    Option<usize>,
+}
+
+/// Finds the cheapest fingering of the notes of one hand with dynamic programming over chords.
+fn finger_hand(notes: &[Note], hand: Hand) -> Vec<Option<Finger>> {
+    let chords = chords(notes);
+    let mut result = vec![None; notes.len()];
+
+    // For each chord: candidate assignments (finger per chord note), the cost of the best path
+    // to them and where that path came from. Chords that can't be fingered have no candidates
+    // and start a new path.
+    let mut layers: Vec<Vec<Candidate>> = Vec::new();
+    for (c, chord) in chords.iter().enumerate() {
+        let pitches = chord.iter().map(|i| notes[*i].pitch).collect::<Vec<_>>();
+        let fixed = chord
+            .iter()
+            .map(|i| notes[*i].finger.map(|(f, _)| f as usize))
+            .collect::<Vec<_>>();
+        let mut candidates = finger_combinations(chord.len(), hand)
+            .into_iter()
+            .filter(|fingers| {
+                fingers
+                    .iter()
+                    .zip(&fixed)
+                    .all(|(f, x)| x.is_none_or(|x| x == *f))
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            candidates = finger_combinations(chord.len(), hand);
+        }
+
+        let previous = c.checked_sub(1).map(|p| {
+            (
+                &layers[p],
+                chords[p]
+                    .iter()
+                    .map(|i| notes[*i].pitch)
+                    .collect::<Vec<_>>(),
+            )
+        });
+        let layer = candidates
+            .into_iter()
+            .map(|fingers| {
+                let own = chord_cost(&pitches, &fingers, hand);
+                let best_previous = previous.as_ref().and_then(|(prev_layer, prev_pitches)| {
+                    prev_layer
+                        .iter()
+                        .enumerate()
+                        .map(|(j, prev)| {
+                            let step = transition_cost(
+                                prev_pitches,
+                                &prev.fingers,
+                                &pitches,
+                                &fingers,
+                                hand,
+                            );
+                            (prev.cost + step, j)
+                        })
+                        .min_by(|a, b| a.0.total_cmp(&b.0))
+                });
+                match best_previous {
+                    Some((cost, from)) => Candidate {
+                        fingers,
+                        cost: cost + own,
+                        from: Some(from),
+                    },
+                    None => Candidate {
+                        fingers,
+                        cost: own,
+                        from: None,
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        layers.push(layer);
+    }
+
+    // Walk back from the cheapest assignment at the end of each path
+    let mut best = None;
+    for (chord, layer) in chords.iter().zip(&layers).rev() {
+        let index = best
+            .or_else(|| (0..layer.len()).min_by(|a, b| layer[*a].cost.total_cmp(&layer[*b].cost)));
+        let Some(index) = index
+        else {
+            continue;
+        };
+        let candidate = &layer[index];
+        for (note, finger) in chord.iter().zip(&candidate.fingers) {
+            result[*note] = Some(FINGERS[*finger]);
+        }
+        best = candidate.from;
+    }
+    result
+}
+
+/// All ways to play `n` notes (sorted by pitch) with distinct fingers, as indices into
+/// `FINGERS`. The right hand plays low notes with low fingers, the left hand the other way.
+fn finger_combinations(n: usize, hand: Hand) -> Vec<Vec<usize>> {
+    if n > 5 {
+        return Vec::new();
+    }
+    let mut result = Vec::new();
+    for mask in 0u32..32 {
+        if mask.count_ones() as usize != n {
+            continue;
+        }
+        let mut fingers = (0..5).filter(|f| mask & (1 << f) != 0).collect::<Vec<_>>();
+        if hand == Hand::Left {
+            fingers.reverse();
+        }
+        result.push(fingers);
+    }
+    result
+}
+
+/// Comfortable (min, max) and relaxed (min, max) distances in halfsteps between two fingers of
+/// the right hand, after Parncutt et al. Indexed by the lower and higher finger.
+fn finger_span(low: usize, high: usize) -> ((f32, f32), (f32, f32)) {
+    match (low, high) {
+        (0, 1) => ((-3.0, 8.0), (1.0, 5.0)),
+        (0, 2) => ((-2.0, 10.0), (3.0, 7.0)),
+        (0, 3) => ((-1.0, 12.0), (5.0, 9.0)),
+        (0, 4) => ((1.0, 13.0), (7.0, 10.0)),
+        (1, 2) => ((1.0, 3.0), (1.0, 2.0)),
+        (1, 3) => ((1.0, 5.0), (3.0, 4.0)),
+        (1, 4) => ((2.0, 8.0), (5.0, 6.0)),
+        (2, 3) => ((1.0, 2.0), (1.0, 2.0)),
+        (2, 4) => ((1.0, 5.0), (3.0, 4.0)),
+        _ => ((1.0, 3.0), (1.0, 2.0)),
+    }
+}
+
+fn is_black_key(pitch: Pitch) -> bool {
+    matches!(pitch.to_midi().rem_euclid(12), 1 | 3 | 6 | 8 | 10)
+}
+
+/// Cost of moving from `a` played with finger `fa` to `b` played with `fb`.
+fn pair_cost(a: Pitch, fa: usize, b: Pitch, fb: usize, hand: Hand) -> f32 {
+    // Mirror the left hand so both hands can use the right hand table
+    let mut distance = (b - a).halfsteps();
+    if hand == Hand::Left {
+        distance = -distance;
+    }
+
+    let mut cost = 0.0;
+    if fa == fb {
+        if distance != 0.0 {
+            cost += 4.0 + distance.abs() * 0.5;
+        }
+    }
+    else {
+        let (low, high, distance) = if fa < fb {
+            (fa, fb, distance)
+        }
+        else {
+            (fb, fa, -distance)
+        };
+        let ((min_comfortable, max_comfortable), (min_relaxed, max_relaxed)) =
+            finger_span(low, high);
+        cost += 2.0 * (min_comfortable - distance).max(0.0);
+        cost += 2.0 * (distance - max_comfortable).max(0.0);
+        cost += (min_relaxed - distance).max(0.0);
+        cost += (distance - max_relaxed).max(0.0);
+        // Thumb passing under or fingers crossing over the thumb
+        if low == 0 && distance < 0.0 {
+            cost += 1.0;
+        }
+    }
+
+    if fb == 0 && is_black_key(b) {
+        cost += 2.0;
+    }
+    if fb == 4 && is_black_key(b) && !is_black_key(a) {
+        cost += 1.0;
+    }
+    cost
+}
+
+fn chord_cost(pitches: &[Pitch], fingers: &[usize], hand: Hand) -> f32 {
+    let mut cost = pitches
+        .iter()
+        .zip(fingers)
+        .filter(|(pitch, finger)| **finger == 0 && is_black_key(**pitch))
+        .count() as f32
+        * 2.0;
+    for i in 1..pitches.len() {
+        cost += pair_cost(pitches[i - 1], fingers[i - 1], pitches[i], fingers[i], hand);
+    }
+    cost
+}
+
+fn transition_cost(
+    prev_pitches: &[Pitch],
+    prev_fingers: &[usize],
+    pitches: &[Pitch],
+    fingers: &[usize],
+    hand: Hand,
+) -> f32 {
+    let mut cost = 0.0;
+    for (a, fa) in prev_pitches.iter().zip(prev_fingers) {
+        for (b, fb) in pitches.iter().zip(fingers) {
+            cost += pair_cost(*a, *fa, *b, *fb, hand);
+        }
+    }
+    cost / (prev_pitches.len() * pitches.len()) as f32
+}
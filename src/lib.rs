@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 #![feature(new_range_api)]
 
+pub mod audio;
 pub mod instrument;
 pub mod note;
 pub mod score;
@@ -54,3 +54,97 @@ impl From<f32> for Fraction {
 impl From<f64> for Fraction {
     fn from(f: f64) -> Self { Fraction::from_f64(f) }
 }
+
+/// How a note is played, written above or below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Articulation {
+    Staccato,
+    Staccatissimo,
+    Accent,
+    Tenuto,
+    Marcato,
+    Fermata,
+    /// Alternates with the next note of the key.
+    Trill,
+    /// Main note, the note below, main note.
+    Mordent,
+    /// Main note, the note above, main note.
+    InvertedMordent,
+    /// Note above, main note, note below, main note.
+    Turn,
+    /// Repeated with this many beams through the stem, 1 plays eighths and 3 thirty-seconds.
+    /// MusicXML allows up to `MAX_TREMOLO_BEAMS`, more are played like that many.
+    Tremolo(u8),
+    /// A short note played just before the beat of the following note.
+    Grace,
+    /// Natural harmonic, sounds an octave higher.
+    Harmonic,
+    /// Slides into the note from below.
+    Slide,
+    HammerOn,
+    PullOff,
+}
+
+impl Articulation {
+    pub const MAX_TREMOLO_BEAMS: u8 = 8;
+
+    /// Factor for the sounding length of the note.
+    pub fn duration_factor(&self) -> f64 {
+        match self {
+            Articulation::Staccato => 0.5,
+            Articulation::Staccatissimo => 0.25,
+            Articulation::Marcato => 0.75,
+            Articulation::Fermata => 2.0,
+            _ => 1.0,
+        }
+    }
+    /// Added to the velocity of the note.
+    pub fn velocity_change(&self) -> i8 {
+        match self {
+            Articulation::Accent => 20,
+            Articulation::Marcato => 30,
+            Articulation::Tenuto => 5,
+            Articulation::Grace => -15,
+            Articulation::HammerOn | Articulation::PullOff => -20,
+            _ => 0,
+        }
+    }
+
+    /// The parent of the MusicXML element and the element, e.g. `("articulations", "staccato")`.
+    /// The parent is `note` for `<grace/>`, `notations` for elements directly in `<notations>`,
+    /// or `articulations`, `ornaments` or `technical`, which are themselves in `<notations>`.
+    pub fn musicxml(&self) -> (&'static str, &'static str) {
+        match self {
+            Articulation::Staccato => ("articulations", "staccato"),
+            Articulation::Staccatissimo => ("articulations", "staccatissimo"),
+            Articulation::Accent => ("articulations", "accent"),
+            Articulation::Tenuto => ("articulations", "tenuto"),
+            Articulation::Marcato => ("articulations", "strong-accent"),
+            Articulation::Fermata => ("notations", "fermata"),
+            Articulation::Trill => ("ornaments", "trill-mark"),
+            Articulation::Mordent => ("ornaments", "mordent"),
+            Articulation::InvertedMordent => ("ornaments", "inverted-mordent"),
+            Articulation::Turn => ("ornaments", "turn"),
+            Articulation::Tremolo(_) => ("ornaments", "tremolo"),
+            Articulation::Grace => ("note", "grace"),
+            Articulation::Harmonic => ("technical", "harmonic"),
+            Articulation::Slide => ("notations", "slide"),
+            Articulation::HammerOn => ("technical", "hammer-on"),
+            Articulation::PullOff => ("technical", "pull-off"),
+        }
+    }
+    /// How guitar tabs write it, next to the fret number.
+    pub fn tab_symbol(&self) -> Option<&'static str> {
+        match self {
+            Articulation::HammerOn => Some("h"),
+            Articulation::PullOff => Some("p"),
+            Articulation::Slide => Some("/"),
+            Articulation::Harmonic => Some("<>"),
+            Articulation::Trill => Some("tr"),
+            Articulation::Staccato => Some("."),
+            Articulation::Accent => Some(">"),
+            _ => None,
+        }
+    }
+}
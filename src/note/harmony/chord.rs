@@ -0,0 +1,75 @@
+use super::{Chroma, Interval, Pitch};
+
+/// A chord as a root and the intervals above it. The root itself is implied.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chord {
+    pub root: Chroma,
+    pub intervals: Vec<Interval>,
+}
+
+impl Chord {
+    pub fn new(root: Chroma, intervals: impl Into<Vec<Interval>>) -> Self {
+        Chord {
+            root,
+            intervals: intervals.into(),
+        }
+    }
+
+    pub fn major(root: Chroma) -> Self {
+        Chord::new(root, [Interval::MAJOR_THIRD, Interval::FIFTH])
+    }
+    pub fn minor(root: Chroma) -> Self {
+        Chord::new(root, [Interval::MINOR_THIRD, Interval::FIFTH])
+    }
+    pub fn diminished(root: Chroma) -> Self {
+        Chord::new(root, [Interval::MINOR_THIRD, Interval::DIMINISHED_FIFTH])
+    }
+    pub fn augmented(root: Chroma) -> Self {
+        Chord::new(root, [Interval::MAJOR_THIRD, Interval::MINOR_SIXTH])
+    }
+    pub fn sus2(root: Chroma) -> Self {
+        Chord::new(root, [Interval::MAJOR_SECOND, Interval::FIFTH])
+    }
+    pub fn sus4(root: Chroma) -> Self { Chord::new(root, [Interval::FOURTH, Interval::FIFTH]) }
+    pub fn dominant_seventh(root: Chroma) -> Self {
+        Chord::new(root, [
+            Interval::MAJOR_THIRD,
+            Interval::FIFTH,
+            Interval::MINOR_SEVENTH,
+        ])
+    }
+    pub fn major_seventh(root: Chroma) -> Self {
+        Chord::new(root, [
+            Interval::MAJOR_THIRD,
+            Interval::FIFTH,
+            Interval::MAJOR_SEVENTH,
+        ])
+    }
+    pub fn minor_seventh(root: Chroma) -> Self {
+        Chord::new(root, [
+            Interval::MINOR_THIRD,
+            Interval::FIFTH,
+            Interval::MINOR_SEVENTH,
+        ])
+    }
+
+    /// The midi chromas (0-11) of the root and all intervals.
+    pub fn pitch_classes(&self) -> impl Iterator<Item = u8> + '_ {
+        let root = self.root.to_midi_chroma() as i32;
+        std::iter::once(root)
+            .chain(
+                self.intervals
+                    .iter()
+                    .map(move |i| root + i.halfsteps().round() as i32),
+            )
+            .map(|c| c.rem_euclid(12) as u8)
+    }
+    pub fn contains(&self, pitch: Pitch) -> bool {
+        let class = pitch.to_midi().rem_euclid(12) as u8;
+        self.pitch_classes().any(|c| c == class)
+    }
+    pub fn is_root(&self, pitch: Pitch) -> bool {
+        pitch.to_midi().rem_euclid(12) == (self.root.to_midi_chroma() as i32).rem_euclid(12)
+    }
+}
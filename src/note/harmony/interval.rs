@@ -52,6 +52,10 @@ impl Interval {
     pub fn from_halfsteps(halfsteps: f32) -> Self { Interval(halfsteps) }
 }
 
+impl std::ops::Neg for Interval {
+    type Output = Interval;
+    fn neg(self) -> Self::Output { Interval(-self.0) }
+}
 impl std::ops::Add<Interval> for Interval {
     type Output = Interval;
     fn add(self, rhs: Interval) -> Self::Output { Interval(self.0 + rhs.0) }
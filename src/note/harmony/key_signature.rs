@@ -1,3 +1,7 @@
+use std::fmt::Display;
+
+use super::{Interval, Pitch};
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct KeySignature {
@@ -5,6 +9,19 @@ pub struct KeySignature {
     pub major: bool,
 }
 
+/// Pitch classes of the natural notes C D E F G A B.
+const NATURALS: [i8; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MAJOR_SCALE: [i8; 7] = NATURALS;
+
+impl Default for KeySignature {
+    fn default() -> Self {
+        KeySignature {
+            flats_sharps: 0,
+            major: true,
+        }
+    }
+}
+
 impl KeySignature {
     pub fn from_midi(flats_sharps: i8, major: bool) -> Self {
         KeySignature {
@@ -12,4 +29,143 @@ impl KeySignature {
             major,
         }
     }
+
+    /// Pitch class of the tonic, 0 for C.
+    pub fn tonic(&self) -> i8 {
+        let major = self.relative_major_tonic();
+        if self.major {
+            major
+        }
+        else {
+            (major + 9).rem_euclid(12)
+        }
+    }
+    fn relative_major_tonic(&self) -> i8 { (7 * self.flats_sharps as i32).rem_euclid(12) as i8 }
+    /// Letter of the relative major's tonic, 0 for C up to 6 for B.
+    fn relative_major_step(&self) -> u8 { (4 * self.flats_sharps as i32).rem_euclid(7) as u8 }
+
+    /// Pitch classes of the seven notes of the key, starting with the relative major's tonic.
+    pub fn scale(&self) -> [i8; 7] {
+        MAJOR_SCALE.map(|step| (self.relative_major_tonic() + step).rem_euclid(12))
+    }
+    pub fn contains(&self, pitch: Pitch) -> bool {
+        self.scale()
+            .contains(&(pitch.to_midi().rem_euclid(12) as i8))
+    }
+
+    /// The key with the same mode whose tonic is `interval` away, with the fewest accidentals.
+    pub fn transposed(&self, interval: Interval) -> KeySignature {
+        let shift = interval.0.round() as i32;
+        let tonic = (self.relative_major_tonic() as i32 + shift).rem_euclid(12);
+        // Keys a fifth apart differ by one accidental, so 7 steps of fifths per halfstep
+        let sharps = (tonic * 7).rem_euclid(12) as i8;
+        let flats_sharps = match sharps {
+            0..=5 => sharps,
+            // F# or Gb, stay on the side of the original key
+            6 if self.flats_sharps >= 0 => 6,
+            _ => sharps - 12,
+        };
+        KeySignature {
+            flats_sharps,
+            major: self.major,
+        }
+    }
+
+    /// How `pitch` is written in this key. Notes outside the key alter a neighbouring note of
+    /// the key, whichever needs fewer accidentals, sharps in sharp keys and flats in flat keys.
+    pub fn spell(&self, pitch: Pitch) -> Spelling {
+        let midi = pitch.to_midi();
+        let class = midi.rem_euclid(12) as i8;
+        let scale = self.scale();
+        let spelled = |degree: usize, alter: i8| {
+            let step = (self.relative_major_step() as usize + degree) % 7;
+            let scale_alter = (scale[degree] - NATURALS[step] + 6).rem_euclid(12) - 6;
+            (step, scale_alter + alter)
+        };
+        let degree_of = |class: i8| scale.iter().position(|c| *c == class.rem_euclid(12));
+
+        let (step, alter) = match degree_of(class) {
+            Some(degree) => spelled(degree, 0),
+            None => {
+                let sharp = degree_of(class - 1).map(|degree| spelled(degree, 1));
+                let flat = degree_of(class + 1).map(|degree| spelled(degree, -1));
+                match (sharp, flat) {
+                    (Some(sharp), Some(flat)) => {
+                        if sharp.1.abs() < flat.1.abs()
+                            || (sharp.1.abs() == flat.1.abs() && self.flats_sharps >= 0)
+                        {
+                            sharp
+                        }
+                        else {
+                            flat
+                        }
+                    }
+                    (sharp, flat) => sharp.or(flat).unwrap_or((0, class)),
+                }
+            }
+        };
+        let octave = (midi - NATURALS[step] as i32 - alter as i32).div_euclid(12) - 1;
+
+        Spelling {
+            step: step as u8,
+            alter,
+            octave: octave as i8,
+        }
+    }
+
+    /// Moves `pitch` by `steps` notes of the key, keeping chromatic alterations and cents.
+    pub fn step_pitch(&self, pitch: Pitch, steps: i32) -> Pitch {
+        let midi = pitch.0.floor() as i32;
+        let cents = pitch.0 - midi as f32;
+        let scale = self.scale();
+
+        // Scale note at or below the pitch
+        let (mut degree, mut base) = (0..12)
+            .find_map(|down| {
+                let candidate = midi - down;
+                let class = candidate.rem_euclid(12) as i8;
+                scale
+                    .iter()
+                    .position(|c| *c == class)
+                    .map(|degree| (degree as i32, candidate))
+            })
+            .unwrap_or((0, midi));
+        let alteration = midi - base;
+
+        for _ in 0..steps.abs() {
+            let next = (degree + steps.signum()).rem_euclid(7);
+            let interval = (scale[next as usize] - scale[degree as usize]).rem_euclid(12) as i32;
+            base += if steps > 0 { interval } else { interval - 12 };
+            degree = next;
+        }
+        Pitch((base + alteration) as f32 + cents)
+    }
+}
+
+/// A written note, e.g. Eb4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spelling {
+    /// Letter, 0 for C up to 6 for B.
+    pub step:   u8,
+    /// Sharps (positive) or flats (negative).
+    pub alter:  i8,
+    pub octave: i8,
+}
+
+impl Spelling {
+    pub fn letter(&self) -> char { ['C', 'D', 'E', 'F', 'G', 'A', 'B'][self.step as usize % 7] }
+}
+
+impl Display for Spelling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let accidental = if self.alter >= 0 { "#" } else { "b" };
+        write!(
+            f,
+            "{}{}{}",
+            self.letter(),
+            accidental.repeat(self.alter.unsigned_abs() as usize),
+            self.octave
+        )
+    }
 }
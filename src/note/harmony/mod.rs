@@ -1,8 +1,10 @@
+mod chord;
 mod chroma;
 mod interval;
 mod key_signature;
 mod pitch;
 
+pub use chord::*;
 pub use chroma::*;
 pub use interval::*;
 pub use key_signature::*;
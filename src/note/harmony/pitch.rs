@@ -14,7 +14,7 @@ impl Pitch {
 
     pub fn from_chroma_octave(chroma: impl Into<Chroma>, octave: i32) -> Self {
         let chroma = chroma.into().to_midi_chroma() as i32;
-        Pitch::from_midi(chroma + octave * 12)
+        Pitch::from_midi(chroma + (octave + 1) * 12)
     }
     pub fn chroma(&self) -> Chroma {
         Chroma::from_midi_chroma((self.to_midi() % 12) as u8).unwrap()
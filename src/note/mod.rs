@@ -6,9 +6,22 @@ pub mod articulation;
 pub mod harmony;
 pub mod rhythm;
 
+/// Identifies a note inside its part, independent of its position in `Part::notes`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoteId(pub u64);
+impl NoteId {
+    /// Notes that haven't been given an id by their part yet.
+    pub const UNASSIGNED: NoteId = NoteId(0);
+}
+
 #[derive(Debug, Default, Clone, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Note {
+    /// Unassigned in scores saved before notes had ids, see `Score::assign_ids`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub id: NoteId,
+
     // Timing
     pub time:     rhythm::Time,
     pub duration: rhythm::Duration,
@@ -24,4 +37,10 @@ pub struct Note {
     pub channel: Option<u8>,
     pub aftertouch: BTreeMap<rhythm::Time, articulation::Velocity>,
     pub bend: BTreeMap<rhythm::Time, f32>,
+
+    // Notation
+    /// Voice inside the part, 0 is the upper voice. `None` until assigned or inferred.
+    pub voice: Option<u8>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub articulations: Vec<articulation::Articulation>,
 }
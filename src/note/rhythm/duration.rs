@@ -31,7 +31,7 @@ impl serde::Serialize for Duration {
 impl<'de> serde::Deserialize<'de> for Duration {
     fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         let time = f64::deserialize(deserializer)?;
-        Ok(Duration((time * Duration::BEAT as f64) as i64))
+        Ok(Duration((time * Duration::BEAT as f64).round() as i64))
     }
 }
 
@@ -17,6 +17,13 @@ impl TimeGrid {
         let i = (time - self.start) / self.step;
         Some(self.start + self.step * i)
     }
+    /// Index and time of the grid line nearest to `time`.
+    pub fn nearest(&self, time: Time) -> (i64, Time) {
+        let offset = (time - self.start).0;
+        let step = self.step.0.max(1);
+        let i = (offset + step / 2).div_euclid(step);
+        (i, self.start + self.step * i)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -1,9 +1,11 @@
 mod duration;
 mod grid;
+mod tempo_map;
 mod time;
 
 pub use duration::*;
 pub use grid::*;
+pub use tempo_map::*;
 pub use time::*;
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
@@ -0,0 +1,67 @@
+use super::{Duration, Tempo, Time};
+
+/// Converts between musical time and seconds. Time zero is at zero seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// Tempo changes with the number of seconds at which they happen, sorted by time.
+    /// The first change is always at `Time::ZERO`.
+    changes: Vec<(Time, f64, Tempo)>,
+}
+
+impl Default for TempoMap {
+    fn default() -> Self { TempoMap::new(&[]) }
+}
+
+impl TempoMap {
+    /// Used until the first tempo change.
+    pub const DEFAULT_TEMPO: Tempo = Tempo(120.0);
+
+    pub fn new(tempo_map: &[(Time, Tempo)]) -> Self {
+        let mut events = tempo_map
+            .iter()
+            .filter(|(_, tempo)| tempo.0 > 0.0)
+            .copied()
+            .collect::<Vec<_>>();
+        events.sort_by_key(|(time, _)| *time);
+
+        let initial = events
+            .iter()
+            .rev()
+            .find(|(time, _)| *time <= Time::ZERO)
+            .map(|(_, tempo)| *tempo)
+            .unwrap_or(TempoMap::DEFAULT_TEMPO);
+
+        let mut changes = vec![(Time::ZERO, 0.0, initial)];
+        for (time, tempo) in events.into_iter().filter(|(time, _)| *time > Time::ZERO) {
+            let (last_time, last_seconds, last_tempo) = *changes.last().unwrap();
+            let seconds = last_seconds + seconds_per_beat(last_tempo) * (time - last_time).beats();
+            changes.push((time, seconds, tempo));
+        }
+        TempoMap { changes }
+    }
+
+    fn change_at_time(&self, time: Time) -> (Time, f64, Tempo) {
+        let i = self.changes.partition_point(|(t, ..)| *t <= time);
+        self.changes[i.saturating_sub(1)]
+    }
+
+    pub fn tempo_at(&self, time: Time) -> Tempo { self.change_at_time(time).2 }
+    pub fn seconds_at(&self, time: Time) -> f64 {
+        let (change_time, seconds, tempo) = self.change_at_time(time);
+        seconds + seconds_per_beat(tempo) * (time - change_time).beats()
+    }
+    pub fn time_at(&self, seconds: f64) -> Time {
+        let i = self.changes.partition_point(|(_, s, _)| *s <= seconds);
+        let (change_time, change_seconds, tempo) = self.changes[i.saturating_sub(1)];
+        change_time + Duration::from_beats_f64((seconds - change_seconds) / seconds_per_beat(tempo))
+    }
+    /// The length of `duration` in seconds if it starts at `time`.
+    pub fn seconds_between(&self, time: Time, duration: Duration) -> f64 {
+        self.seconds_at(time + duration) - self.seconds_at(time)
+    }
+    pub fn changes(&self) -> impl Iterator<Item = (Time, Tempo)> + '_ {
+        self.changes.iter().map(|(time, _, tempo)| (*time, *tempo))
+    }
+}
+
+fn seconds_per_beat(tempo: Tempo) -> f64 { 60.0 / tempo.0 as f64 }
@@ -20,7 +20,7 @@ impl serde::Serialize for Time {
 impl<'de> serde::Deserialize<'de> for Time {
     fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         let time = f64::deserialize(deserializer)?;
-        Ok(Time((time * Duration::BEAT as f64) as i64))
+        Ok(Time((time * Duration::BEAT as f64).round() as i64))
     }
 }
 
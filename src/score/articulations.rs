@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use super::Part;
+use crate::note::articulation::{Articulation, Velocity};
+use crate::note::harmony::{Interval, Pitch};
+use crate::note::rhythm::{Duration, Time};
+use crate::note::Note;
+
+impl Part {
+    /// The notes played for `note` with its articulations applied: shortened, louder, or
+    /// ornaments written out with the neighbouring notes of the key.
+    pub fn articulated_notes(&self, note: &Note) -> Vec<Note> {
+        let mut played = note.clone();
+        if note.articulations.is_empty() {
+            return vec![played];
+        }
+
+        let factor = note
+            .articulations
+            .iter()
+            .map(Articulation::duration_factor)
+            .product::<f64>();
+        let velocity_change = note
+            .articulations
+            .iter()
+            .map(|a| a.velocity_change() as i32)
+            .sum::<i32>();
+        played.duration = note.duration * factor;
+        played.velocity = Velocity((note.velocity.0 as i32 + velocity_change).clamp(1, 127) as u8);
+
+        let key = self.key_signature_at(note.time);
+        let upper = key.step_pitch(note.pitch, 1);
+        let lower = key.step_pitch(note.pitch, -1);
+        let mut notes = vec![played];
+        for articulation in &note.articulations {
+            notes = notes
+                .into_iter()
+                .flat_map(|note| match articulation {
+                    Articulation::Trill => {
+                        let count = (note.duration / Duration::THIRTY_SECOND).max(1);
+                        let pitches = (0..count)
+                            .map(|i| {
+                                if i % 2 == 0 {
+                                    note.pitch
+                                }
+                                else {
+                                    upper
+                                }
+                            })
+                            .collect::<Vec<_>>();
+                        split(&note, &pitches, Duration::THIRTY_SECOND)
+                    }
+                    Articulation::Mordent | Articulation::InvertedMordent => {
+                        let neighbour = match articulation {
+                            Articulation::Mordent => lower,
+                            _ => upper,
+                        };
+                        let step = Duration::THIRTY_SECOND.min(note.duration / 4);
+                        split(&note, &[note.pitch, neighbour, note.pitch], step)
+                    }
+                    Articulation::Turn => split(
+                        &note,
+                        &[upper, note.pitch, lower, note.pitch],
+                        note.duration / 4,
+                    ),
+                    Articulation::Tremolo(beams) => {
+                        let beams = (*beams).clamp(1, Articulation::MAX_TREMOLO_BEAMS);
+                        let step = Duration::EIGHTH / 2i64.pow(beams as u32 - 1);
+                        let count = (note.duration / step).max(1) as usize;
+                        split(&note, &vec![note.pitch; count], step)
+                    }
+                    Articulation::Grace => {
+                        let mut grace = note.clone();
+                        grace.duration = Duration::THIRTY_SECOND;
+                        grace.time = (note.time - grace.duration).max(Time::ZERO);
+                        vec![grace]
+                    }
+                    Articulation::Harmonic => {
+                        let mut harmonic = note.clone();
+                        harmonic.pitch += Interval::OCTAVE;
+                        vec![harmonic]
+                    }
+                    Articulation::Slide if note.bend.is_empty() => {
+                        let mut slide = note.clone();
+                        slide.bend = BTreeMap::from([
+                            (Time::ZERO, -2.0),
+                            (Time::ZERO + Duration::THIRTY_SECOND.min(note.duration), 0.0),
+                        ]);
+                        vec![slide]
+                    }
+                    _ => vec![note],
+                })
+                .collect();
+        }
+        notes
+    }
+}
+
+/// Plays `pitches` one after another, each `step` long, the last one until the end of `note`.
+fn split(note: &Note, pitches: &[Pitch], step: Duration) -> Vec<Note> {
+    let end = note.time + note.duration;
+    pitches
+        .iter()
+        .enumerate()
+        .map(|(i, pitch)| {
+            let time = note.time + step * i as i64;
+            let mut piece = note.clone();
+            piece.time = time;
+            piece.pitch = *pitch;
+            piece.duration = if i + 1 == pitches.len() {
+                end - time
+            }
+            else {
+                step
+            };
+            piece.bend.clear();
+            piece
+        })
+        .filter(|piece| piece.duration > Duration::default())
+        .collect()
+}
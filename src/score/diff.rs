@@ -0,0 +1,677 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use super::lyrics::Lyric;
+use super::spanners::Spanner;
+use super::{beat_length, Part, Score};
+use crate::note::harmony::{KeySignature, Pitch};
+use crate::note::rhythm::{Duration, Tempo, Time, TimeSignature};
+use crate::note::{Note, NoteId};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NoteChange {
+    Added(Note),
+    Removed(Note),
+    Changed { old: Note, new: Note },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    PartAdded {
+        part: usize,
+        description: String,
+    },
+    PartRemoved {
+        part: usize,
+        description: String,
+    },
+    /// The part is now at `to`, not counting parts that only shifted because others were
+    /// added or removed.
+    PartMoved {
+        from: usize,
+        to: usize,
+        description: String,
+    },
+    /// `bar` and `beat` count from 1, in the part the note is in after the change, or was in
+    /// before it for removed notes.
+    Note {
+        part:   usize,
+        bar:    usize,
+        beat:   f64,
+        change: NoteChange,
+    },
+    TimeSignatures {
+        part: usize,
+        old:  Vec<(Time, TimeSignature)>,
+        new:  Vec<(Time, TimeSignature)>,
+    },
+    KeySignatures {
+        part: usize,
+        old:  Vec<(Time, KeySignature)>,
+        new:  Vec<(Time, KeySignature)>,
+    },
+    Tempo {
+        old: Vec<(Time, Tempo)>,
+        new: Vec<(Time, Tempo)>,
+    },
+    /// Anything else about a part, e.g. "instrument" or "spanners".
+    PartProperty {
+        part: usize,
+        name: &'static str,
+    },
+    /// Anything else about the score, e.g. "form".
+    ScoreProperty(&'static str),
+}
+
+/// The differences between two versions of a score. Parts are matched by the notes they share,
+/// then by description and instrument. Notes are matched by id, or by time and pitch if they
+/// have none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScoreDiff {
+    pub changes: Vec<Change>,
+}
+
+impl ScoreDiff {
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+}
+
+/// A change that couldn't be merged. The merged score has our version.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conflict {
+    /// Both sides changed the note differently, or one changed it and the other deleted it.
+    Note {
+        part:   usize,
+        base:   Option<Box<Note>>,
+        ours:   Option<Box<Note>>,
+        theirs: Option<Box<Note>>,
+    },
+    /// Both sides changed the same property differently, e.g. "time signatures".
+    PartProperty {
+        part: usize,
+        name: &'static str,
+    },
+    ScoreProperty(&'static str),
+    /// One side removed the part and the other changed it. Parts moved differently on both
+    /// sides are a `ScoreProperty("part order")` conflict.
+    PartRemoved {
+        part: usize,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Merge {
+    pub score:     Score,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Score {
+    /// What changed from `self` to `other`.
+    pub fn diff(&self, other: &Score) -> ScoreDiff {
+        let mut changes = Vec::new();
+        let matches = match_parts(self, other);
+        let moved = moved_parts(&matches);
+        for (from, old) in self.parts.iter().enumerate() {
+            let Some(to) = matches[from]
+            else {
+                changes.push(Change::PartRemoved {
+                    part: from,
+                    description: old.description.clone(),
+                });
+                continue;
+            };
+            let new = &other.parts[to];
+            if moved.contains(&from) {
+                changes.push(Change::PartMoved {
+                    from,
+                    to,
+                    description: new.description.clone(),
+                });
+            }
+            diff_part(to, old, new, &mut changes);
+        }
+        for (part, new) in other.parts.iter().enumerate() {
+            if !matches.contains(&Some(part)) {
+                changes.push(Change::PartAdded {
+                    part,
+                    description: new.description.clone(),
+                });
+            }
+        }
+        if self.tempo_map != other.tempo_map {
+            changes.push(Change::Tempo {
+                old: self.tempo_map.clone(),
+                new: other.tempo_map.clone(),
+            });
+        }
+        if self.text_events != other.text_events {
+            changes.push(Change::ScoreProperty("text events"));
+        }
+        if self.form != other.form {
+            changes.push(Change::ScoreProperty("form"));
+        }
+        ScoreDiff { changes }
+    }
+
+    /// Combines the changes made from `base` to `ours` and to `theirs`. Notes changed on one
+    /// side only, notes added on either side and edits to different properties merge cleanly;
+    /// everything else is a conflict and keeps our version. Parts are matched like in `diff`,
+    /// so parts removed or moved on one side don't get mixed up with others.
+    pub fn merge(base: &Score, ours: &Score, theirs: &Score) -> Merge {
+        let mut conflicts = Vec::new();
+        let (our_parts, their_parts) = (match_parts(base, ours), match_parts(base, theirs));
+
+        // The order of the parts both kept, taken from the side that changed it
+        let kept = (0..base.parts.len())
+            .filter(|part| our_parts[*part].is_some() && their_parts[*part].is_some())
+            .collect::<Vec<_>>();
+        let order = |matches: &[Option<usize>]| {
+            let mut order = kept.clone();
+            order.sort_by_key(|part| matches[*part]);
+            order
+        };
+        let (our_order, their_order) = (order(&our_parts), order(&their_parts));
+        if our_order != kept && their_order != kept && our_order != their_order {
+            conflicts.push(Conflict::ScoreProperty("part order"));
+        }
+        let (first, first_parts, second, second_parts) =
+            match our_order == kept && their_order != kept {
+                true => (theirs, &their_parts, ours, &our_parts),
+                false => (ours, &our_parts, theirs, &their_parts),
+            };
+
+        // Parts in the order of the first side, followed by what only the second has
+        let base_part =
+            |matches: &[Option<usize>], part| matches.iter().position(|m| *m == Some(part));
+        let mut sources = Vec::new();
+        for (part, added) in first.parts.iter().enumerate() {
+            sources.push(match base_part(first_parts, part) {
+                Some(part) => PartSource::Base(part),
+                None => PartSource::Added(added),
+            });
+        }
+        for (part, added) in second.parts.iter().enumerate() {
+            match base_part(second_parts, part) {
+                Some(part) if first_parts[part].is_none() => sources.push(PartSource::Base(part)),
+                Some(_) => (),
+                // Added on both sides, keep both unless they are the same
+                None if !sources.contains(&PartSource::Added(added)) => {
+                    sources.push(PartSource::Added(added))
+                }
+                None => (),
+            }
+        }
+
+        let mut parts = Vec::new();
+        for source in sources {
+            let part = parts.len();
+            let b = match source {
+                PartSource::Added(added) => {
+                    parts.push(added.clone());
+                    continue;
+                }
+                PartSource::Base(b) => b,
+            };
+            let o = our_parts[b].map(|o| &ours.parts[o]);
+            let t = their_parts[b].map(|t| &theirs.parts[t]);
+            match (o, t) {
+                (Some(o), Some(t)) => {
+                    parts.push(merge_part(part, &base.parts[b], o, t, &mut conflicts))
+                }
+                // Removed on one side
+                (Some(kept), None) | (None, Some(kept)) => {
+                    if *kept != base.parts[b] {
+                        conflicts.push(Conflict::PartRemoved { part });
+                        parts.push(kept.clone());
+                    }
+                }
+                (None, None) => (),
+            }
+        }
+
+        let mut score = Score {
+            parts,
+            ..ours.clone()
+        };
+        match merge_value(&base.tempo_map, &ours.tempo_map, &theirs.tempo_map) {
+            Some(tempo_map) => score.tempo_map = tempo_map,
+            None => conflicts.push(Conflict::ScoreProperty("tempo")),
+        }
+        score.text_events = merge_set(&base.text_events, &ours.text_events, &theirs.text_events);
+        score.text_events.sort_by_key(|event| event.time);
+        score.form = merge_set(&base.form, &ours.form, &theirs.form);
+        score.form.sort_by_key(|(time, _)| *time);
+        Merge { score, conflicts }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NoteKey {
+    Id(NoteId),
+    /// Time and pitch bits for notes without an id.
+    Position(Time, u32),
+}
+
+fn note_key(note: &Note) -> NoteKey {
+    match note.id {
+        NoteId::UNASSIGNED => NoteKey::Position(note.time, note.pitch.0.to_bits()),
+        id => NoteKey::Id(id),
+    }
+}
+
+/// Notes by key and how many notes with the same key came before, so notes without an id at
+/// the same time and pitch are told apart by their order.
+fn notes_by_key(part: &Part) -> BTreeMap<(NoteKey, usize), &Note> {
+    let mut counts = BTreeMap::new();
+    part.notes
+        .iter()
+        .map(|note| {
+            let key = note_key(note);
+            let count = counts.entry(key).or_insert(0);
+            *count += 1;
+            ((key, *count - 1), note)
+        })
+        .collect()
+}
+
+/// Where a part of the merged score comes from.
+#[derive(PartialEq)]
+enum PartSource<'a> {
+    Base(usize),
+    Added(&'a Part),
+}
+
+/// For every part of `base`, the part of `other` it became or `None` if it was removed. Parts
+/// match by the notes they share, then by description and instrument, then by position.
+fn match_parts(base: &Score, other: &Score) -> Vec<Option<usize>> {
+    let other_notes = other.parts.iter().map(notes_by_key).collect::<Vec<_>>();
+    let mut pairs = Vec::new();
+    for (b, old) in base.parts.iter().enumerate() {
+        let old_notes = notes_by_key(old);
+        for (o, new) in other.parts.iter().enumerate() {
+            let shared = other_notes[o]
+                .iter()
+                .filter(|(key, note)| old_notes.get(key) == Some(note))
+                .count();
+            let named = old.description == new.description && old.instrument == new.instrument;
+            if shared > 0 || named {
+                pairs.push(((shared, named, b == o), b, o));
+            }
+        }
+    }
+    // Best first, earlier parts first among equals
+    pairs.sort_by_key(|(score, ..)| std::cmp::Reverse(*score));
+    let mut matches = vec![None; base.parts.len()];
+    let mut taken = vec![false; other.parts.len()];
+    for (_, b, o) in pairs {
+        if matches[b].is_none() && !taken[o] {
+            matches[b] = Some(o);
+            taken[o] = true;
+        }
+    }
+    matches
+}
+
+/// The fewest matched parts that explain the new order when taken out and put back elsewhere.
+fn moved_parts(matches: &[Option<usize>]) -> Vec<usize> {
+    let matched = matches
+        .iter()
+        .enumerate()
+        .filter_map(|(old, new)| Some((old, (*new)?)))
+        .collect::<Vec<_>>();
+    // Longest run of parts that kept their order, ending at each part
+    let mut longest = vec![(1, None); matched.len()];
+    for i in 0..matched.len() {
+        for j in 0..i {
+            if matched[j].1 < matched[i].1 && longest[j].0 + 1 > longest[i].0 {
+                longest[i] = (longest[j].0 + 1, Some(j));
+            }
+        }
+    }
+    let mut stayed = Vec::new();
+    let mut next = (0..matched.len()).max_by_key(|i| longest[*i].0);
+    while let Some(i) = next {
+        stayed.push(matched[i].0);
+        next = longest[i].1;
+    }
+    matched
+        .iter()
+        .map(|(old, _)| *old)
+        .filter(|old| !stayed.contains(old))
+        .collect()
+}
+
+/// Bar and beat of `time` in `part`, counting from 1.
+fn location(part: &Part, time: Time) -> (usize, f64) {
+    let (bar, (start, signature)) = part
+        .bars_until(time + Duration(1))
+        .enumerate()
+        .last()
+        .unwrap_or((0, (Time::ZERO, TimeSignature::default())));
+    let beat = (time - start).beats() / beat_length(signature).beats();
+    (bar + 1, beat + 1.0)
+}
+
+fn diff_part(part: usize, old: &Part, new: &Part, changes: &mut Vec<Change>) {
+    let (old_notes, new_notes) = (notes_by_key(old), notes_by_key(new));
+    let mut note_changes = Vec::new();
+    for (key, note) in &old_notes {
+        match new_notes.get(key) {
+            None => note_changes.push((old, NoteChange::Removed((*note).clone()))),
+            Some(changed) if changed != note => note_changes.push((new, NoteChange::Changed {
+                old: (*note).clone(),
+                new: (*changed).clone(),
+            })),
+            Some(_) => (),
+        }
+    }
+    for (key, note) in &new_notes {
+        if !old_notes.contains_key(key) {
+            note_changes.push((new, NoteChange::Added((*note).clone())));
+        }
+    }
+    let time = |change: &NoteChange| match change {
+        NoteChange::Added(note) | NoteChange::Removed(note) => note.time,
+        NoteChange::Changed { new, .. } => new.time,
+    };
+    note_changes.sort_by_key(|(_, change)| time(change));
+    changes.extend(note_changes.into_iter().map(|(located, change)| {
+        let (bar, beat) = location(located, time(&change));
+        Change::Note {
+            part,
+            bar,
+            beat,
+            change,
+        }
+    }));
+
+    if old.time_signature != new.time_signature {
+        changes.push(Change::TimeSignatures {
+            part,
+            old: old.time_signature.clone(),
+            new: new.time_signature.clone(),
+        });
+    }
+    if old.key_signature != new.key_signature {
+        changes.push(Change::KeySignatures {
+            part,
+            old: old.key_signature.clone(),
+            new: new.key_signature.clone(),
+        });
+    }
+    let properties = [
+        ("description", old.description != new.description),
+        ("instrument", old.instrument != new.instrument),
+        ("rests", old.rests != new.rests),
+        ("spanners", old.spanners != new.spanners),
+        ("dynamics", old.dynamics != new.dynamics),
+        ("lyrics", old.lyrics != new.lyrics),
+    ];
+    for (name, changed) in properties {
+        if changed {
+            changes.push(Change::PartProperty { part, name });
+        }
+    }
+}
+
+fn merge_part(
+    part: usize,
+    base: &Part,
+    ours: &Part,
+    theirs: &Part,
+    conflicts: &mut Vec<Conflict>,
+) -> Part {
+    let (b, o, t) = (notes_by_key(base), notes_by_key(ours), notes_by_key(theirs));
+    let mut keys = b
+        .keys()
+        .chain(o.keys())
+        .chain(t.keys())
+        .copied()
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    let mut notes = Vec::new();
+    let mut added_by_them = Vec::new();
+    for key in keys {
+        let (b, o, t) = (
+            b.get(&key).copied(),
+            o.get(&key).copied(),
+            t.get(&key).copied(),
+        );
+        match (b, o, t) {
+            // Both added a note with the same id, keep both
+            (None, Some(o), Some(t)) if o != t => {
+                notes.push(o.clone());
+                added_by_them.push(t.clone());
+            }
+            _ => match merge_value(&b, &o, &t) {
+                Some(merged) => notes.extend(merged.cloned()),
+                None => {
+                    conflicts.push(Conflict::Note {
+                        part,
+                        base: b.cloned().map(Box::new),
+                        ours: o.cloned().map(Box::new),
+                        theirs: t.cloned().map(Box::new),
+                    });
+                    notes.extend(o.cloned());
+                }
+            },
+        }
+    }
+
+    notes.sort_by_key(|n| n.time);
+    let mut merged = Part {
+        notes,
+        next_id: ours.next_id.max(theirs.next_id),
+        ..ours.clone()
+    };
+    // Their notes that got a new id take their spanners and lyrics along
+    let mut new_ids = BTreeMap::new();
+    for note in added_by_them {
+        let id = note.id;
+        new_ids.insert(id, merged.add_note(note));
+    }
+    let new_id = |id: NoteId| new_ids.get(&id).copied().unwrap_or(id);
+    let their_spanners = theirs
+        .spanners
+        .iter()
+        .map(|spanner| Spanner {
+            start: new_id(spanner.start),
+            end: new_id(spanner.end),
+            ..*spanner
+        })
+        .collect::<Vec<_>>();
+    let their_lyrics = theirs
+        .lyrics
+        .iter()
+        .map(|lyric| Lyric {
+            note: new_id(lyric.note),
+            ..lyric.clone()
+        })
+        .collect::<Vec<_>>();
+
+    macro_rules! merge_field {
+        ($field:ident, $name:literal) => {
+            match merge_value(&base.$field, &ours.$field, &theirs.$field) {
+                Some(value) => merged.$field = value,
+                None => conflicts.push(Conflict::PartProperty { part, name: $name }),
+            }
+        };
+    }
+    merge_field!(description, "description");
+    merge_field!(instrument, "instrument");
+    merge_field!(time_signature, "time signatures");
+    merge_field!(key_signature, "key signatures");
+    merge_field!(dynamics, "dynamics");
+    merged.rests = merge_set(&base.rests, &ours.rests, &theirs.rests);
+    merged.rests.sort();
+    merged.spanners = merge_set(&base.spanners, &ours.spanners, &their_spanners);
+    merged.lyrics = merge_set(&base.lyrics, &ours.lyrics, &their_lyrics);
+    merged.remove_dangling_spanners();
+    merged
+}
+
+/// The side that changed, `None` if both changed differently.
+fn merge_value<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    }
+    else if ours == base {
+        Some(theirs.clone())
+    }
+    else {
+        None
+    }
+}
+
+/// Items kept by both sides plus the ones either side added.
+fn merge_set<T: PartialEq + Clone>(base: &[T], ours: &[T], theirs: &[T]) -> Vec<T> {
+    let mut merged = ours
+        .iter()
+        .filter(|item| theirs.contains(item) || !base.contains(item))
+        .cloned()
+        .collect::<Vec<_>>();
+    merged.extend(
+        theirs
+            .iter()
+            .filter(|item| !base.contains(item) && !ours.contains(item))
+            .cloned(),
+    );
+    merged
+}
+
+impl Display for NoteChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |note: &Note| {
+            format!(
+                "{} ({} beats, velocity {})",
+                note.pitch,
+                note.duration.beats(),
+                note.velocity
+            )
+        };
+        match self {
+            NoteChange::Added(note) => write!(f, "added {}", describe(note)),
+            NoteChange::Removed(note) => write!(f, "removed {}", describe(note)),
+            NoteChange::Changed { old, new } => {
+                let mut parts = Vec::new();
+                if old.pitch != new.pitch {
+                    parts.push(format!("pitch -> {}", new.pitch));
+                }
+                if old.time != new.time {
+                    let by = (new.time - old.time).beats();
+                    parts.push(format!("moved by {by:+} beats"));
+                }
+                if old.duration != new.duration {
+                    parts.push(format!(
+                        "duration {} -> {} beats",
+                        old.duration.beats(),
+                        new.duration.beats()
+                    ));
+                }
+                if old.velocity != new.velocity {
+                    parts.push(format!("velocity {} -> {}", old.velocity, new.velocity));
+                }
+                if parts.is_empty() {
+                    parts.push("details".to_string());
+                }
+                write!(f, "changed {}: {}", old.pitch, parts.join(", "))
+            }
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes = |old: String, new: String| {
+            let or_none = |list: String| {
+                if list.is_empty() {
+                    "none".to_string()
+                }
+                else {
+                    list
+                }
+            };
+            format!("{} -> {}", or_none(old), or_none(new))
+        };
+        match self {
+            Change::PartAdded { part, description } => {
+                write!(f, "Part {part}: added \"{description}\"")
+            }
+            Change::PartRemoved { part, description } => {
+                write!(f, "Part {part}: removed \"{description}\"")
+            }
+            Change::PartMoved {
+                from,
+                to,
+                description,
+            } => write!(f, "Part {from}: moved \"{description}\" to {to}"),
+            Change::Note {
+                part,
+                bar,
+                beat,
+                change,
+            } => write!(f, "Part {part}, bar {bar} beat {beat}: {change}"),
+            Change::TimeSignatures { part, old, new } => {
+                let list = |signatures: &[(Time, TimeSignature)]| {
+                    signatures
+                        .iter()
+                        .map(|(_, s)| format!("{}/{}", s.numerator, s.subdivision))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                write!(
+                    f,
+                    "Part {part}: time signatures {}",
+                    changes(list(old), list(new))
+                )
+            }
+            Change::KeySignatures { part, old, new } => {
+                let list = |keys: &[(Time, KeySignature)]| {
+                    keys.iter()
+                        .map(|(_, key)| key_name(key))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                write!(
+                    f,
+                    "Part {part}: key signatures {}",
+                    changes(list(old), list(new))
+                )
+            }
+            Change::Tempo { old, new } => {
+                let list = |tempos: &[(Time, Tempo)]| {
+                    tempos
+                        .iter()
+                        .map(|(_, tempo)| format!("{} bpm", tempo.0))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                write!(f, "Tempo {}", changes(list(old), list(new)))
+            }
+            Change::PartProperty { part, name } => write!(f, "Part {part}: {name} changed"),
+            Change::ScoreProperty(name) => write!(f, "Score: {name} changed"),
+        }
+    }
+}
+
+/// E.g. "Eb major".
+fn key_name(key: &KeySignature) -> String {
+    let tonic = key.spell(Pitch::from_midi(60 + key.tonic() as i32));
+    let accidental = if tonic.alter >= 0 { "#" } else { "b" };
+    format!(
+        "{}{} {}",
+        tonic.letter(),
+        accidental.repeat(tonic.alter.unsigned_abs() as usize),
+        if key.major { "major" } else { "minor" }
+    )
+}
+
+impl Display for ScoreDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
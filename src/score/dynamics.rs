@@ -0,0 +1,257 @@
+use std::fmt::Display;
+
+use super::spanners::{Spanner, SpannerKind};
+use super::Part;
+use crate::note::articulation::Velocity;
+use crate::note::rhythm::Time;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Dynamic {
+    PPP,
+    PP,
+    P,
+    MP,
+    MF,
+    F,
+    FF,
+    FFF,
+    /// Sforzando, accents the note it is on and returns to the previous level.
+    SFZ,
+    /// Forte-piano, starts loud and drops to piano right away.
+    FP,
+}
+
+impl Dynamic {
+    /// The levels from softest to loudest, without the accents.
+    pub const LEVELS: [Dynamic; 8] = [
+        Dynamic::PPP,
+        Dynamic::PP,
+        Dynamic::P,
+        Dynamic::MP,
+        Dynamic::MF,
+        Dynamic::F,
+        Dynamic::FF,
+        Dynamic::FFF,
+    ];
+
+    pub fn is_accent(&self) -> bool { matches!(self, Dynamic::SFZ | Dynamic::FP) }
+    /// The level that holds after this marking, `previous` for sfz.
+    fn level_after(self, previous: Dynamic) -> Dynamic {
+        match self {
+            Dynamic::SFZ => previous,
+            Dynamic::FP => Dynamic::P,
+            level => level,
+        }
+    }
+    fn step(self, steps: i32) -> Dynamic {
+        let index = Dynamic::LEVELS.iter().position(|d| *d == self).unwrap_or(4) as i32;
+        Dynamic::LEVELS[(index + steps).clamp(0, 7) as usize]
+    }
+}
+
+impl Display for Dynamic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Dynamic::PPP => "ppp",
+            Dynamic::PP => "pp",
+            Dynamic::P => "p",
+            Dynamic::MP => "mp",
+            Dynamic::MF => "mf",
+            Dynamic::F => "f",
+            Dynamic::FF => "ff",
+            Dynamic::FFF => "fff",
+            Dynamic::SFZ => "sfz",
+            Dynamic::FP => "fp",
+        })
+    }
+}
+
+/// Velocities used for playing back dynamic markings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynamicMapping {
+    /// For ppp up to fff.
+    pub levels: [Velocity; 8],
+    /// The accented note of sfz and fp.
+    pub accent: Velocity,
+}
+
+impl Default for DynamicMapping {
+    fn default() -> Self {
+        DynamicMapping {
+            levels: [16, 33, 49, 64, 80, 96, 112, 127].map(Velocity),
+            accent: Velocity(112),
+        }
+    }
+}
+
+impl DynamicMapping {
+    pub fn velocity(&self, dynamic: Dynamic) -> Velocity {
+        match Dynamic::LEVELS.iter().position(|d| *d == dynamic) {
+            Some(index) => self.levels[index],
+            None => self.accent,
+        }
+    }
+    /// The level closest to `velocity`.
+    pub fn dynamic(&self, velocity: Velocity) -> Dynamic {
+        let index = (0..8)
+            .min_by_key(|i| (self.levels[*i].0 as i32 - velocity.0 as i32).abs())
+            .unwrap_or(4);
+        Dynamic::LEVELS[index]
+    }
+}
+
+impl Part {
+    /// The level in effect at `time`, mf if there are no markings before it.
+    pub fn dynamic_at(&self, time: Time) -> Dynamic {
+        let mut markings = self
+            .dynamics
+            .iter()
+            .filter(|(start, _)| *start <= time)
+            .collect::<Vec<_>>();
+        markings.sort_by_key(|(start, _)| *start);
+        markings
+            .into_iter()
+            .fold(Dynamic::MF, |level, (_, marking)| {
+                marking.level_after(level)
+            })
+    }
+
+    /// Velocities for the notes from the dynamic markings. Hairpins move linearly to the
+    /// next marking after them, or one level up or down if there is none.
+    pub fn dynamic_velocities(&self, mapping: &DynamicMapping) -> Vec<Velocity> {
+        let indices = self.note_indices();
+        let hairpins = self
+            .spanners
+            .iter()
+            .filter_map(|spanner| {
+                let steps = match spanner.kind {
+                    SpannerKind::Crescendo => 1,
+                    SpannerKind::Diminuendo => -1,
+                    _ => return None,
+                };
+                let (start, end) = spanner.notes_in(&indices)?;
+                let (start, end) = (self.notes[start].time, self.notes[end].time);
+                let from = self.dynamic_at(start);
+                let to = self
+                    .dynamics
+                    .iter()
+                    .filter(|(time, dynamic)| *time >= end && !dynamic.is_accent())
+                    .min_by_key(|(time, _)| *time)
+                    .map_or(from.step(steps), |(_, dynamic)| *dynamic);
+                Some((start, end, from, to))
+            })
+            .collect::<Vec<_>>();
+
+        self.notes
+            .iter()
+            .map(|note| {
+                let accent = self
+                    .dynamics
+                    .iter()
+                    .any(|(time, dynamic)| *time == note.time && dynamic.is_accent());
+                if accent {
+                    return mapping.accent;
+                }
+                let hairpin = hairpins
+                    .iter()
+                    .find(|(start, end, ..)| *start <= note.time && note.time <= *end);
+                match hairpin {
+                    Some((start, end, from, to)) if start < end => {
+                        let t = (note.time - *start).beats() / (*end - *start).beats();
+                        let from = mapping.velocity(*from).0 as f64;
+                        let to = mapping.velocity(*to).0 as f64;
+                        Velocity((from + (to - from) * t).round() as u8)
+                    }
+                    _ => mapping.velocity(self.dynamic_at(note.time)),
+                }
+            })
+            .collect()
+    }
+    /// Sets the velocity of every note from the dynamic markings.
+    pub fn apply_dynamics(&mut self, mapping: &DynamicMapping) {
+        let velocities = self.dynamic_velocities(mapping);
+        for (note, velocity) in self.notes.iter_mut().zip(velocities) {
+            note.velocity = velocity;
+        }
+    }
+
+    /// Replaces the dynamic markings with ones read from the note velocities, e.g. after a
+    /// MIDI import. Each bar gets the level of its average velocity, and runs of bars getting
+    /// steadily louder or softer become hairpins.
+    pub fn infer_dynamics(&mut self, mapping: &DynamicMapping) {
+        self.assign_ids();
+        self.spanners.retain(|spanner| {
+            !matches!(
+                spanner.kind,
+                SpannerKind::Crescendo | SpannerKind::Diminuendo
+            )
+        });
+
+        let end = self
+            .notes
+            .iter()
+            .map(|n| n.time + n.duration)
+            .max()
+            .unwrap_or(Time::ZERO);
+        let bars = self
+            .bars_until(end)
+            .map(|(time, _)| time)
+            .collect::<Vec<_>>();
+        // First note, last note and level of every bar with notes
+        let mut levels = Vec::new();
+        for (i, start) in bars.iter().enumerate() {
+            let bar_end = bars.get(i + 1).copied().unwrap_or(end);
+            let mut notes = (0..self.notes.len())
+                .filter(|n| *start <= self.notes[*n].time && self.notes[*n].time < bar_end)
+                .collect::<Vec<_>>();
+            if notes.is_empty() {
+                continue;
+            }
+            notes.sort_by_key(|n| self.notes[*n].time);
+            let average = notes
+                .iter()
+                .map(|n| self.notes[*n].velocity.0 as u32)
+                .sum::<u32>()
+                / notes.len() as u32;
+            levels.push((
+                notes[0],
+                notes[notes.len() - 1],
+                mapping.dynamic(Velocity(average as u8)),
+            ));
+        }
+
+        let mut dynamics = Vec::new();
+        let mut i = 0;
+        while i < levels.len() {
+            let (first, _, level) = levels[i];
+            if dynamics.last().is_none_or(|(_, last)| *last != level) {
+                dynamics.push((self.notes[first].time, level));
+            }
+            // Follow the run of bars changing in the same direction
+            let direction = levels.get(i + 1).map(|next| next.2.cmp(&level));
+            let mut run = i;
+            while let (Some(next), Some(direction)) = (levels.get(run + 1), direction) {
+                if direction.is_eq() || next.2.cmp(&levels[run].2) != direction {
+                    break;
+                }
+                run += 1;
+            }
+            if run > i + 1 {
+                let kind = match direction {
+                    Some(std::cmp::Ordering::Greater) => SpannerKind::Crescendo,
+                    _ => SpannerKind::Diminuendo,
+                };
+                let end = levels[run - 1].1;
+                self.spanners
+                    .push(Spanner::new(kind, self.notes[first].id, self.notes[end].id));
+                i = run;
+            }
+            else {
+                i += 1;
+            }
+        }
+        self.dynamics = dynamics;
+    }
+}
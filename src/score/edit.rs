@@ -1,7 +1,13 @@
 use core::range::Range;
+use std::cmp::Ordering;
 
-use crate::note::harmony::Pitch;
-use crate::note::rhythm::Time;
+use super::lyrics::Lyric;
+use super::spanners::Spanner;
+use super::{Part, Score};
+use crate::note::articulation::Velocity;
+use crate::note::harmony::{Interval, KeySignature, Pitch};
+use crate::note::rhythm::{Duration, Tempo, Time, TimeSignature};
+use crate::note::{Note, NoteId};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Cursor {
@@ -9,8 +15,541 @@ pub struct Cursor {
     pub time_range: Range<Time>,
     pub pitch_range: Range<Pitch>,
 }
+impl Cursor {
+    pub fn rectangle(part: usize, time_range: Range<Time>, pitch_range: Range<Pitch>) -> Self {
+        Cursor {
+            part,
+            time_range,
+            pitch_range,
+        }
+    }
+    /// All pitches of a bar.
+    pub fn bar(score: &Score, part: usize, bar: usize) -> Option<Self> {
+        let time_range = score.parts.get(part)?.bar_range(bar)?;
+        Some(Cursor::rectangle(
+            part,
+            time_range,
+            (Pitch(f32::NEG_INFINITY)..Pitch(f32::INFINITY)).into(),
+        ))
+    }
+    /// One pitch over the whole part.
+    pub fn pitch(part: usize, pitch: Pitch) -> Self {
+        Cursor::rectangle(
+            part,
+            (Time::ZERO..Time(i64::MAX)).into(),
+            (pitch..pitch).into(),
+        )
+    }
+
+    /// Whether the note starts inside the time range and its pitch is inside the pitch range,
+    /// including the end.
+    pub fn contains(&self, part: usize, note: &Note) -> bool {
+        part == self.part
+            && self.time_range.contains(&note.time)
+            && self.pitch_range.start <= note.pitch
+            && note.pitch <= self.pitch_range.end
+    }
+
+    /// A cursor around a single note.
+    pub fn around(part: usize, note: &Note) -> Self {
+        Cursor {
+            part,
+            time_range: (note.time..note.time + note.duration).into(),
+            pitch_range: (note.pitch..note.pitch).into(),
+        }
+    }
+}
+
+/// A reversible change to a `Score`.
+///
+/// Edits store everything needed to undo them, so they are created from the score they will
+/// be applied to. Notes of a part are kept sorted by time. They are addressed by id, so the
+/// notes need unique ones (see `Score::assign_ids`), and placed after the note with the id in
+/// `after`, `None` being the start of the part.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// Also adds the spanners and lyrics attached to the note at their index.
+    InsertNote {
+        part:     usize,
+        after:    Option<NoteId>,
+        note:     Note,
+        spanners: Vec<(usize, Spanner)>,
+        lyrics:   Vec<(usize, Lyric)>,
+    },
+    /// Also removes the spanners and lyrics attached to the note, which are kept with their
+    /// index for undo.
+    DeleteNote {
+        part:     usize,
+        after:    Option<NoteId>,
+        note:     Note,
+        spanners: Vec<(usize, Spanner)>,
+        lyrics:   Vec<(usize, Lyric)>,
+    },
+    /// Replaces the note with the id of `old` by `new`, which goes after `to`. It was after
+    /// `from`.
+    UpdateNote {
+        part: usize,
+        from: Option<NoteId>,
+        to:   Option<NoteId>,
+        old:  Note,
+        new:  Note,
+    },
+    SetTimeSignatures {
+        part: usize,
+        old:  Vec<(Time, TimeSignature)>,
+        new:  Vec<(Time, TimeSignature)>,
+    },
+    SetKeySignatures {
+        part: usize,
+        old:  Vec<(Time, KeySignature)>,
+        new:  Vec<(Time, KeySignature)>,
+    },
+    SetTempoMap {
+        old: Vec<(Time, Tempo)>,
+        new: Vec<(Time, Tempo)>,
+    },
+    AddSpanner {
+        part:    usize,
+        spanner: Spanner,
+    },
+    RemoveSpanner {
+        part:    usize,
+        spanner: Spanner,
+    },
+    /// Replaces the whole score, for structural changes like moving sections.
+    SetScore {
+        old: Box<Score>,
+        new: Box<Score>,
+    },
+    /// Applied in order, undone in reverse. Each edit is relative to the score as the previous
+    /// ones left it.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// Notes without an id, or with one that is taken, get a new one.
+    pub fn insert_note(score: &Score, part: usize, mut note: Note) -> Option<Edit> {
+        let target = score.parts.get(part)?;
+        if note.id == NoteId::UNASSIGNED || target.note_index(note.id).is_some() {
+            note.id = target.next_note_id();
+        }
+        let index = target.notes.partition_point(|n| n.time <= note.time);
+        Some(Edit::InsertNote {
+            part,
+            after: index.checked_sub(1).map(|i| target.notes[i].id),
+            note,
+            spanners: Vec::new(),
+            lyrics: Vec::new(),
+        })
+    }
+    pub fn delete_note(score: &Score, part: usize, id: NoteId) -> Option<Edit> {
+        let target = score.parts.get(part)?;
+        let index = target.note_index(id)?;
+        let note = target.notes[index].clone();
+        Some(Edit::DeleteNote {
+            part,
+            after: index.checked_sub(1).map(|i| target.notes[i].id),
+            spanners: target
+                .spanners
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|(_, spanner)| spanner.start == id || spanner.end == id)
+                .collect(),
+            lyrics: target
+                .lyrics
+                .iter()
+                .cloned()
+                .enumerate()
+                .filter(|(_, lyric)| lyric.note == id)
+                .collect(),
+            note,
+        })
+    }
+    /// Deletes several notes of a part in one edit.
+    pub fn delete_notes(score: &Score, part: usize, ids: &[NoteId]) -> Option<Edit> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        // Each deletion sees the notes, spanners and lyrics the previous ones left
+        let mut scratch = score.clone();
+        let mut edits = Vec::new();
+        for id in ids {
+            let edit = Edit::delete_note(&scratch, part, id)?;
+            edit.apply(&mut scratch);
+            edits.push(edit);
+        }
+        Some(Edit::Batch(edits))
+    }
+    /// Changes the note, keeping its id.
+    pub fn update_note(
+        score: &Score,
+        part: usize,
+        id: NoteId,
+        update: impl FnOnce(&mut Note),
+    ) -> Option<Edit> {
+        let target = score.parts.get(part)?;
+        let index = target.note_index(id)?;
+        let notes = &target.notes;
+        let old = notes[index].clone();
+        let mut new = old.clone();
+        update(&mut new);
+        new.id = old.id;
+        // Position after the old note has been removed. It keeps its place among the notes
+        // starting with it, or goes before them when moved earlier and after them when later.
+        let to = match new.time.cmp(&old.time) {
+            Ordering::Equal => index,
+            Ordering::Less => notes.partition_point(|n| n.time < new.time),
+            Ordering::Greater => notes.partition_point(|n| n.time <= new.time) - 1,
+        };
+        // Skips the old note when looking up the one before the new position
+        let without = |i: usize| if i < index { i } else { i + 1 };
+        Some(Edit::UpdateNote {
+            part,
+            from: index.checked_sub(1).map(|i| notes[i].id),
+            to: to.checked_sub(1).map(|i| notes[without(i)].id),
+            old,
+            new,
+        })
+    }
+    pub fn move_note(
+        score: &Score,
+        part: usize,
+        id: NoteId,
+        by: Duration,
+        transpose: Interval,
+    ) -> Option<Edit> {
+        Edit::update_note(score, part, id, |note| {
+            note.time = (note.time + by).max(Time::ZERO);
+            note.pitch += transpose;
+        })
+    }
+    pub fn resize_note(score: &Score, part: usize, id: NoteId, duration: Duration) -> Option<Edit> {
+        Edit::update_note(score, part, id, |note| note.duration = duration)
+    }
+    pub fn set_pitch(score: &Score, part: usize, id: NoteId, pitch: Pitch) -> Option<Edit> {
+        Edit::update_note(score, part, id, |note| note.pitch = pitch)
+    }
+    pub fn set_velocity(
+        score: &Score,
+        part: usize,
+        id: NoteId,
+        velocity: Velocity,
+    ) -> Option<Edit> {
+        Edit::update_note(score, part, id, |note| note.velocity = velocity)
+    }
+
+    /// Sets the time signature from `time` on, `None` removes a change at `time`.
+    pub fn set_time_signature(
+        score: &Score,
+        part: usize,
+        time: Time,
+        signature: Option<TimeSignature>,
+    ) -> Option<Edit> {
+        let old = score.parts.get(part)?.time_signature.clone();
+        Some(Edit::SetTimeSignatures {
+            part,
+            new: with_change(&old, time, signature),
+            old,
+        })
+    }
+    pub fn set_key_signature(
+        score: &Score,
+        part: usize,
+        time: Time,
+        signature: Option<KeySignature>,
+    ) -> Option<Edit> {
+        let old = score.parts.get(part)?.key_signature.clone();
+        Some(Edit::SetKeySignatures {
+            part,
+            new: with_change(&old, time, signature),
+            old,
+        })
+    }
+    pub fn set_tempo(score: &Score, time: Time, tempo: Option<Tempo>) -> Edit {
+        Edit::SetTempoMap {
+            old: score.tempo_map.clone(),
+            new: with_change(&score.tempo_map, time, tempo),
+        }
+    }
+
+    /// Makes a structural change such as `Score::move_section` undoable.
+    pub fn restructure(score: &Score, change: impl FnOnce(&mut Score)) -> Edit {
+        let mut new = score.clone();
+        change(&mut new);
+        Edit::SetScore {
+            old: Box::new(score.clone()),
+            new: Box::new(new),
+        }
+    }
+
+    pub fn add_spanner(score: &Score, part: usize, spanner: Spanner) -> Option<Edit> {
+        score.parts.get(part)?.spanner_notes(&spanner)?;
+        Some(Edit::AddSpanner { part, spanner })
+    }
+
+    /// Panics if a part the edit changes doesn't exist. Notes that are already there or already
+    /// gone are left alone.
+    pub fn apply(&self, score: &mut Score) {
+        match self {
+            Edit::InsertNote {
+                part,
+                after,
+                note,
+                spanners,
+                lyrics,
+            } => {
+                let part = &mut score.parts[*part];
+                part.reserve_id(note.id);
+                if part.note_index(note.id).is_some() {
+                    return;
+                }
+                let index = position(part, *after, note.time);
+                part.notes.insert(index, note.clone());
+                for (index, spanner) in spanners {
+                    part.spanners
+                        .insert((*index).min(part.spanners.len()), *spanner);
+                }
+                for (index, lyric) in lyrics {
+                    part.lyrics
+                        .insert((*index).min(part.lyrics.len()), lyric.clone());
+                }
+            }
+            Edit::DeleteNote { part, note, .. } => {
+                score.parts[*part].remove_note(note.id);
+            }
+            Edit::UpdateNote { part, to, new, .. } => {
+                let part = &mut score.parts[*part];
+                part.reserve_id(new.id);
+                let Some(index) = part.note_index(new.id)
+                else {
+                    return;
+                };
+                part.notes.remove(index);
+                let index = position(part, *to, new.time);
+                part.notes.insert(index, new.clone());
+            }
+            Edit::SetTimeSignatures { part, new, .. } => {
+                score.parts[*part].time_signature = new.clone()
+            }
+            Edit::SetKeySignatures { part, new, .. } => {
+                score.parts[*part].key_signature = new.clone()
+            }
+            Edit::SetTempoMap { new, .. } => score.tempo_map = new.clone(),
+            Edit::AddSpanner { part, spanner } => score.parts[*part].spanners.push(*spanner),
+            Edit::RemoveSpanner { part, spanner } => {
+                let spanners = &mut score.parts[*part].spanners;
+                if let Some(index) = spanners.iter().rposition(|s| s == spanner) {
+                    spanners.remove(index);
+                }
+            }
+            Edit::SetScore { new, .. } => *score = (**new).clone(),
+            Edit::Batch(edits) => edits.iter().for_each(|edit| edit.apply(score)),
+        }
+    }
+
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::InsertNote {
+                part,
+                after,
+                note,
+                spanners,
+                lyrics,
+            } => Edit::DeleteNote {
+                part,
+                after,
+                note,
+                spanners,
+                lyrics,
+            },
+            Edit::DeleteNote {
+                part,
+                after,
+                note,
+                spanners,
+                lyrics,
+            } => Edit::InsertNote {
+                part,
+                after,
+                note,
+                spanners,
+                lyrics,
+            },
+            Edit::UpdateNote {
+                part,
+                from,
+                to,
+                old,
+                new,
+            } => Edit::UpdateNote {
+                part,
+                from: to,
+                to: from,
+                old: new,
+                new: old,
+            },
+            Edit::SetTimeSignatures { part, old, new } => Edit::SetTimeSignatures {
+                part,
+                old: new,
+                new: old,
+            },
+            Edit::SetKeySignatures { part, old, new } => Edit::SetKeySignatures {
+                part,
+                old: new,
+                new: old,
+            },
+            Edit::SetTempoMap { old, new } => Edit::SetTempoMap { old: new, new: old },
+            Edit::AddSpanner { part, spanner } => Edit::RemoveSpanner { part, spanner },
+            Edit::RemoveSpanner { part, spanner } => Edit::AddSpanner { part, spanner },
+            Edit::SetScore { old, new } => Edit::SetScore { old: new, new: old },
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+
+    /// One edit doing `self` and then `next`, e.g. all steps of a drag.
+    pub fn merge(self, next: Edit) -> Edit {
+        match (self, next) {
+            (
+                Edit::UpdateNote {
+                    part, from, old, ..
+                },
+                Edit::UpdateNote {
+                    part: next_part,
+                    to: next_to,
+                    new,
+                    ..
+                },
+            ) if part == next_part && old.id == new.id => Edit::UpdateNote {
+                part,
+                from,
+                to: next_to,
+                old,
+                new,
+            },
+            (Edit::SetTempoMap { old, .. }, Edit::SetTempoMap { new, .. }) => {
+                Edit::SetTempoMap { old, new }
+            }
+            (Edit::Batch(mut edits), next) => {
+                edits.push(next);
+                Edit::Batch(edits)
+            }
+            (edit, next) => Edit::Batch(vec![edit, next]),
+        }
+    }
+
+    /// Cursors around the notes this edit leaves behind.
+    pub fn cursors(&self) -> Vec<Cursor> {
+        match self {
+            Edit::InsertNote { part, note, .. }
+            | Edit::UpdateNote {
+                part, new: note, ..
+            } => {
+                vec![Cursor::around(*part, note)]
+            }
+            Edit::Batch(edits) => edits.iter().flat_map(Edit::cursors).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Index right after the note `after`, or where a note at `time` goes when that would break the
+/// order by time, e.g. because the note is gone.
+fn position(part: &Part, after: Option<NoteId>, time: Time) -> usize {
+    let notes = &part.notes;
+    let index = match after {
+        Some(id) => part.note_index(id).map(|i| i + 1),
+        None => Some(0),
+    };
+    index
+        .filter(|i| {
+            i.checked_sub(1).is_none_or(|i| notes[i].time <= time)
+                && notes.get(*i).is_none_or(|n| time <= n.time)
+        })
+        .unwrap_or_else(|| notes.partition_point(|n| n.time <= time))
+}
+
+fn with_change<T: Clone>(changes: &[(Time, T)], time: Time, value: Option<T>) -> Vec<(Time, T)> {
+    let mut changes = changes
+        .iter()
+        .filter(|(t, _)| *t != time)
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Some(value) = value {
+        let index = changes.partition_point(|(t, _)| *t < time);
+        changes.insert(index, (time, value));
+    }
+    changes
+}
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    edit:    Edit,
+    cursors: Vec<Cursor>,
+    /// Consecutive edits with the same id are undone together.
+    merge:   Option<u64>,
+}
 
 #[derive(Default, Debug, Clone)]
 pub struct EditState {
     pub cursors: Vec<Cursor>,
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+}
+
+impl EditState {
+    /// Applies `edit` and moves the cursors to the notes it changed.
+    pub fn apply(&mut self, score: &mut Score, edit: Edit) { self.apply_merged(score, edit, None) }
+
+    /// Like `apply`, but merges with the previous edit if it has the same `merge` id, so a drag
+    /// made of many small moves is undone in one step.
+    pub fn apply_merged(&mut self, score: &mut Score, edit: Edit, merge: Option<u64>) {
+        edit.apply(score);
+        self.redo.clear();
+        let cursors = std::mem::replace(&mut self.cursors, edit.cursors());
+
+        match self.undo.last_mut() {
+            Some(last) if merge.is_some() && last.merge == merge => {
+                let previous = std::mem::replace(&mut last.edit, Edit::Batch(Vec::new()));
+                last.edit = previous.merge(edit);
+            }
+            _ => self.undo.push(HistoryEntry {
+                edit,
+                cursors,
+                merge,
+            }),
+        }
+    }
+
+    /// Ends merging, the next edit starts a new undo step even with the same id.
+    pub fn commit(&mut self) {
+        if let Some(last) = self.undo.last_mut() {
+            last.merge = None;
+        }
+    }
+
+    pub fn can_undo(&self) -> bool { !self.undo.is_empty() }
+    pub fn can_redo(&self) -> bool { !self.redo.is_empty() }
+
+    pub fn undo(&mut self, score: &mut Score) -> bool {
+        let Some(mut entry) = self.undo.pop()
+        else {
+            return false;
+        };
+        entry.edit.inverse().apply(score);
+        std::mem::swap(&mut self.cursors, &mut entry.cursors);
+        entry.merge = None;
+        self.redo.push(entry);
+        true
+    }
+    pub fn redo(&mut self, score: &mut Score) -> bool {
+        let Some(mut entry) = self.redo.pop()
+        else {
+            return false;
+        };
+        entry.edit.apply(score);
+        std::mem::swap(&mut self.cursors, &mut entry.cursors);
+        self.undo.push(entry);
+        true
+    }
 }
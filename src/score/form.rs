@@ -0,0 +1,562 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{bar_length, Part, Score};
+use crate::note::rhythm::{Duration, Time, TimeRange};
+use crate::note::NoteId;
+
+/// How far to play after a D.C. or D.S.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JumpUntil {
+    End,
+    Fine,
+    /// Up to the To Coda sign, then on from the coda.
+    Coda,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FormMarking {
+    /// Start of a repeated region. Without one repeats go back to the previous repeat barline
+    /// or the start.
+    RepeatStart,
+    /// Repeat barline, the region is played `times` times in total.
+    RepeatEnd {
+        times: u8,
+    },
+    /// First, second, ... ending, played on the listed passes of the repeat.
+    Volta {
+        endings: Vec<u8>,
+        length:  Duration,
+    },
+    Segno,
+    /// Start of the coda.
+    Coda,
+    /// Where to go to the coda after a D.C. or D.S. al Coda.
+    ToCoda,
+    Fine,
+    DaCapo(JumpUntil),
+    DalSegno(JumpUntil),
+    /// Start of a named section such as "Verse".
+    Section(String),
+    /// Boxed letter or number for finding a place in rehearsal.
+    Rehearsal(String),
+}
+
+impl FormMarking {
+    /// Section names and rehearsal marks, which only label the timeline.
+    pub fn is_label(&self) -> bool {
+        matches!(self, FormMarking::Section(_) | FormMarking::Rehearsal(_))
+    }
+    /// Whether it acts at the end of what comes before it, so it goes before markings
+    /// starting something at the same time.
+    fn is_closing(&self) -> bool {
+        matches!(
+            self,
+            FormMarking::RepeatEnd { .. }
+                | FormMarking::ToCoda
+                | FormMarking::Fine
+                | FormMarking::DaCapo(_)
+                | FormMarking::DalSegno(_)
+        )
+    }
+}
+
+/// A stretch of the written score in playback order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub source: TimeRange,
+    /// Where it starts when played.
+    pub start:  Time,
+    /// Pass of the repeat it is played in, starting at 1.
+    pub pass:   u8,
+}
+
+impl Segment {
+    pub fn end(&self) -> Time { self.start + (self.source.end - self.source.start) }
+    pub fn contains(&self, written: Time) -> bool {
+        self.source.start <= written && written < self.source.end
+    }
+    pub fn played_time(&self, written: Time) -> Time { self.start + (written - self.source.start) }
+}
+
+/// The playback order of a score with repeats and jumps, mapping played to written time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Unfolding {
+    pub segments: Vec<Segment>,
+}
+
+impl Unfolding {
+    pub fn length(&self) -> Duration {
+        self.segments
+            .last()
+            .map_or(Duration::default(), |segment| segment.end() - Time::ZERO)
+    }
+    /// When `written` is heard, once for every pass over it.
+    pub fn played_times(&self, written: Time) -> Vec<Time> {
+        self.segments
+            .iter()
+            .filter(|segment| segment.contains(written))
+            .map(|segment| segment.played_time(written))
+            .collect()
+    }
+    pub fn written_time(&self, played: Time) -> Option<Time> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| segment.start <= played && played < segment.end())?;
+        Some(segment.source.start + (played - segment.start))
+    }
+}
+
+impl Score {
+    /// The form markings sorted by time, closing ones first.
+    pub fn form_markings(&self) -> Vec<&(Time, FormMarking)> {
+        let mut markings = self.form.iter().collect::<Vec<_>>();
+        markings.sort_by_key(|(time, marking)| (*time, !marking.is_closing()));
+        markings
+    }
+
+    /// Follows the repeats, endings and jumps. After a D.C. or D.S. repeats are played once
+    /// and only the last ending of each volta is taken.
+    pub fn unfolding(&self) -> Unfolding {
+        let markings = self.form_markings();
+        let end = markings
+            .last()
+            .map_or(self.end(), |(time, _)| self.end().max(*time));
+        let find = |wanted: &FormMarking| {
+            markings
+                .iter()
+                .find(|(_, marking)| marking == wanted)
+                .map(|(time, _)| *time)
+        };
+        let segno = find(&FormMarking::Segno).unwrap_or(Time::ZERO);
+        let coda = find(&FormMarking::Coda);
+        let last_ending = |time: Time, length: Duration| {
+            !markings.iter().any(|(start, marking)| {
+                *start == time + length && matches!(marking, FormMarking::Volta { .. })
+            })
+        };
+
+        let mut segments = Vec::new();
+        let mut played = Time::ZERO;
+        let mut position = Time::ZERO;
+        let mut repeat_start = Time::ZERO;
+        let mut pass = 1;
+        let mut jumped = None;
+        // Guards against markings that would loop forever
+        for _ in 0..10_000 {
+            let segment_pass = pass;
+            // Where this segment stops and where playing resumes, if at all
+            let mut stop = (end, None);
+            // Where the last ending of a repeat finishes and the passes start over
+            let mut repeat_done = None;
+            for (time, marking) in markings.iter().filter(|(time, _)| *time >= position) {
+                let (time, passed) = (*time, *time > position);
+                match marking {
+                    FormMarking::RepeatStart => repeat_start = time,
+                    FormMarking::RepeatEnd { times } if passed => {
+                        if jumped.is_none() && pass < *times {
+                            pass += 1;
+                            stop = (time, Some(repeat_start));
+                        }
+                        else {
+                            // The next repeat starts here unless it has its own start
+                            repeat_start = time;
+                            pass = 1;
+                            stop = (time, Some(time));
+                        }
+                        break;
+                    }
+                    FormMarking::Volta { endings, length } => {
+                        let plays = match jumped {
+                            None => endings.contains(&pass),
+                            Some(_) => last_ending(time, *length),
+                        };
+                        if !plays {
+                            stop = (time, Some(time + *length));
+                            break;
+                        }
+                        if jumped.is_none() && pass > 1 && last_ending(time, *length) {
+                            pass = 1;
+                            repeat_start = time + *length;
+                            repeat_done = Some(time + *length);
+                        }
+                    }
+                    FormMarking::DaCapo(until) | FormMarking::DalSegno(until)
+                        if passed && jumped.is_none() =>
+                    {
+                        let target = match marking {
+                            FormMarking::DaCapo(_) => Time::ZERO,
+                            _ => segno,
+                        };
+                        jumped = Some(*until);
+                        repeat_start = target;
+                        pass = 1;
+                        stop = (time, Some(target));
+                        break;
+                    }
+                    FormMarking::ToCoda if passed && jumped == Some(JumpUntil::Coda) => {
+                        if let Some(coda) = coda {
+                            jumped = Some(JumpUntil::End);
+                            stop = (time, Some(coda));
+                            break;
+                        }
+                    }
+                    FormMarking::Fine if passed && jumped == Some(JumpUntil::Fine) => {
+                        stop = (time, None);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+
+            let (stop, resume) = stop;
+            // The last ending belongs to the pass taking it, what follows to the first pass
+            let split = repeat_done.filter(|done| *done < stop).unwrap_or(stop);
+            for (from, until, pass) in [(position, split, segment_pass), (split, stop, 1)] {
+                if until > from {
+                    segments.push(Segment {
+                        source: (from..until).into(),
+                        start: played,
+                        pass,
+                    });
+                    played += until - from;
+                }
+            }
+            match resume {
+                Some(resume) if resume < end => position = resume,
+                _ => break,
+            }
+        }
+        Unfolding { segments }
+    }
+
+    /// The score as it is played, with every repeat written out. Notes played more than once
+    /// get new ids for the later passes and take the lyrics of the verse for their pass.
+    /// Only sections and rehearsal marks are kept.
+    pub fn unfold(&self) -> Score {
+        let unfolding = self.unfolding();
+        let segments = &unfolding.segments;
+        let mut score = self.clone();
+
+        for (part, source) in score.parts.iter_mut().zip(&self.parts) {
+            let copy = copy_segments(source, segments, &mut BTreeSet::new(), true);
+            (part.notes, part.spanners, part.lyrics, part.rests) =
+                (copy.notes, copy.spanners, copy.lyrics, copy.rests);
+
+            part.time_signature = unfold_timeline(&source.time_signature, segments, |time| {
+                state_before(&source.time_signature, time)
+            });
+            part.key_signature = unfold_timeline(&source.key_signature, segments, |time| {
+                state_before(&source.key_signature, time)
+            });
+            part.dynamics = unfold_timeline(&source.dynamics, segments, |time| {
+                (!source.dynamics.is_empty()).then(|| source.dynamic_at(time))
+            });
+        }
+
+        score.tempo_map = unfold_timeline(&self.tempo_map, segments, |time| {
+            (!self.tempo_map.is_empty()).then(|| self.tempo().tempo_at(time))
+        });
+        score.text_events = segments
+            .iter()
+            .flat_map(|segment| {
+                self.text_events
+                    .iter()
+                    .filter(|event| segment.contains(event.time))
+                    .map(|event| {
+                        let mut event = event.clone();
+                        event.time = segment.played_time(event.time);
+                        event
+                    })
+            })
+            .collect();
+        let labels = self
+            .form
+            .iter()
+            .filter(|(_, marking)| marking.is_label())
+            .cloned()
+            .collect::<Vec<_>>();
+        score.form = unfold_timeline(&labels, segments, |_| None);
+        score
+    }
+
+    /// Writes bars that are repeated right after each other once, with repeat barlines, as
+    /// is common in imported MIDI. Repetitions that only differ in their last bar get first and
+    /// second endings. Bars are those of the first part and compared across all parts by
+    /// timing and pitch.
+    pub fn fold_repeats(&self) -> Score {
+        let mut score = self.clone();
+        let Some(first) = self.parts.first()
+        else {
+            return score;
+        };
+        let bars = first
+            .bars_until(self.end())
+            .map(|(start, signature)| TimeRange::from(start..start + bar_length(signature)))
+            .collect::<Vec<_>>();
+        let contents = bars
+            .iter()
+            .map(|bar| {
+                let mut notes = self
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(part, source)| {
+                        source
+                            .notes
+                            .iter()
+                            .filter(|n| bar.start <= n.time && n.time < bar.end)
+                            .map(move |n| {
+                                (part, n.time - bar.start, n.duration, n.pitch.0.to_bits())
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                notes.sort();
+                (bar.end - bar.start, notes)
+            })
+            .collect::<Vec<_>>();
+        let same = |a: usize, b: usize, count: usize| {
+            (0..count).all(|k| contents[a + k] == contents[b + k])
+        };
+        let has_notes =
+            |start: usize, count: usize| (start..start + count).any(|k| !contents[k].1.is_empty());
+
+        // Start bar, bars in the repeat, times played and whether the last bar is an ending
+        let mut folds = Vec::new();
+        let mut i = 0;
+        while i < bars.len() {
+            let remaining = bars.len() - i;
+            let mut best: Option<(usize, usize)> = None;
+            for length in (1..=remaining / 2).filter(|length| has_notes(i, *length)) {
+                // Longer runs continue in the next repeat, as the times are a u8
+                let mut times = 1;
+                while times < u8::MAX as usize
+                    && (times + 1) * length <= remaining
+                    && same(i, i + times * length, length)
+                {
+                    times += 1;
+                }
+                if times > 1 && best.is_none_or(|(l, t)| times * length > l * t) {
+                    best = Some((length, times));
+                }
+            }
+            if let Some((length, times)) = best {
+                folds.push((i, length, times, false));
+                i += length * times;
+                continue;
+            }
+            let ending = (2..=remaining / 2)
+                .rev()
+                .filter(|length| has_notes(i, length - 1))
+                .find(|length| same(i, i + length, length - 1));
+            if let Some(length) = ending {
+                folds.push((i, length, 2, true));
+                i += length * 2;
+                continue;
+            }
+            i += 1;
+        }
+
+        // From the end, so the earlier bars keep their times
+        for (start, length, times, ending) in folds.into_iter().rev() {
+            let repeat = TimeRange::from(bars[start].start..bars[start + length - 1].end);
+            let mut markings = vec![(repeat.start, FormMarking::RepeatStart)];
+            if ending {
+                let first_ending = bars[start + length - 1];
+                let second_ending = bars[start + 2 * length - 1];
+                score.remove_range((repeat.end..bars[start + 2 * length - 2].end).into());
+                markings.extend([
+                    (first_ending.start, FormMarking::Volta {
+                        endings: vec![1],
+                        length:  first_ending.end - first_ending.start,
+                    }),
+                    (repeat.end, FormMarking::RepeatEnd { times: 2 }),
+                    (repeat.end, FormMarking::Volta {
+                        endings: vec![2],
+                        length:  second_ending.end - second_ending.start,
+                    }),
+                ]);
+            }
+            else {
+                score.remove_range((repeat.end..bars[start + length * times - 1].end).into());
+                markings.push((repeat.end, FormMarking::RepeatEnd { times: times as u8 }));
+            }
+            score.form.extend(markings);
+        }
+        score
+            .form
+            .sort_by_key(|(time, marking)| (*time, !marking.is_closing()));
+        score
+    }
+
+    /// Cuts `range` out of every part, the tempo map and the markings, moving everything after
+    /// it earlier. Signatures, dynamics and tempo changes inside the range take effect at its
+    /// start so what follows sounds the same.
+    pub fn remove_range(&mut self, range: TimeRange) {
+        let length = range.end - range.start;
+        if length <= Duration::default() {
+            return;
+        }
+        let shift = |time: &mut Time| {
+            if *time >= range.end {
+                *time -= length;
+            }
+        };
+        for part in &mut self.parts {
+            let removed = part
+                .notes
+                .iter()
+                .filter(|n| range.contains(&n.time))
+                .map(|n| n.id)
+                .collect::<BTreeSet<_>>();
+            part.next_id = part.next_note_id().0;
+            part.notes.retain(|n| !range.contains(&n.time));
+            part.notes.iter_mut().for_each(|n| shift(&mut n.time));
+            part.remove_dangling_spanners();
+            part.lyrics.retain(|l| !removed.contains(&l.note));
+            part.rests.retain(|r| !range.contains(&r.time));
+            part.rests.iter_mut().for_each(|r| shift(&mut r.time));
+            remove_from_timeline(&mut part.time_signature, range, true);
+            remove_from_timeline(&mut part.key_signature, range, true);
+            remove_from_timeline(&mut part.dynamics, range, true);
+        }
+        remove_from_timeline(&mut self.tempo_map, range, true);
+        remove_from_timeline(&mut self.form, range, false);
+        self.text_events.retain(|e| !range.contains(&e.time));
+        self.text_events.iter_mut().for_each(|e| shift(&mut e.time));
+    }
+}
+
+/// Copies of the notes, spanners, lyrics and rests of `source` in every segment, moved to where
+/// the segment is played. Notes keep their id the first time it isn't `used`, and get a new
+/// one otherwise. With `by_pass` only the lyrics of the verse for the pass are kept.
+pub(super) fn copy_segments(
+    source: &Part,
+    segments: &[Segment],
+    used: &mut BTreeSet<NoteId>,
+    by_pass: bool,
+) -> Part {
+    let mut next_id = used
+        .iter()
+        .map(|id| id.0 + 1)
+        .max()
+        .unwrap_or(0)
+        .max(source.next_note_id().0);
+    let (mut notes, mut spanners, mut lyrics, mut rests) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for segment in segments {
+        let mut ids = BTreeMap::new();
+        for note in source.notes.iter().filter(|n| segment.contains(n.time)) {
+            let mut note = note.clone();
+            let id = match used.insert(note.id) && note.id != NoteId::UNASSIGNED {
+                true => note.id,
+                false => {
+                    next_id += 1;
+                    used.insert(NoteId(next_id - 1));
+                    NoteId(next_id - 1)
+                }
+            };
+            ids.insert(note.id, id);
+            note.id = id;
+            note.time = segment.played_time(note.time);
+            notes.push(note);
+        }
+        for spanner in &source.spanners {
+            if let (Some(start), Some(end)) = (ids.get(&spanner.start), ids.get(&spanner.end)) {
+                let mut spanner = *spanner;
+                (spanner.start, spanner.end) = (*start, *end);
+                spanners.push(spanner);
+            }
+        }
+        for (old, new) in &ids {
+            let on_note = source.lyrics.iter().filter(|l| l.note == *old);
+            let verse = segment.pass.saturating_sub(1);
+            let verse = match on_note.clone().any(|l| l.verse == verse) {
+                true => verse,
+                false => 0,
+            };
+            for lyric in on_note.filter(|l| !by_pass || l.verse == verse) {
+                let mut lyric = lyric.clone();
+                lyric.note = *new;
+                if by_pass {
+                    lyric.verse = 0;
+                }
+                lyrics.push(lyric);
+            }
+        }
+        for rest in source.rests.iter().filter(|r| segment.contains(r.time)) {
+            let mut rest = *rest;
+            rest.time = segment.played_time(rest.time);
+            rests.push(rest);
+        }
+    }
+    Part {
+        notes,
+        spanners,
+        lyrics,
+        rests,
+        ..Default::default()
+    }
+}
+
+/// The last event at or before `time`, the default if there only are later ones.
+pub(super) fn state_before<T: Clone + Default>(events: &[(Time, T)], time: Time) -> Option<T> {
+    if events.is_empty() {
+        return None;
+    }
+    let last = events
+        .iter()
+        .filter(|(start, _)| *start <= time)
+        .max_by_key(|(start, _)| *start);
+    Some(last.map_or_else(T::default, |(_, value)| value.clone()))
+}
+
+/// Copies the events of every segment to where it is played. Segments without an event at
+/// their start begin with `state_at` their written start.
+pub(super) fn unfold_timeline<T: Clone>(
+    events: &[(Time, T)],
+    segments: &[Segment],
+    state_at: impl Fn(Time) -> Option<T>,
+) -> Vec<(Time, T)> {
+    let mut unfolded = Vec::new();
+    for segment in segments {
+        if !events.iter().any(|(time, _)| *time == segment.source.start) {
+            if let Some(state) = state_at(segment.source.start) {
+                unfolded.push((segment.start, state));
+            }
+        }
+        unfolded.extend(
+            events
+                .iter()
+                .filter(|(time, _)| segment.contains(*time))
+                .map(|(time, value)| (segment.played_time(*time), value.clone())),
+        );
+    }
+    unfolded
+}
+
+/// Drops the events in `range` and moves later ones earlier. With `carry` the last dropped
+/// event moves to the start of the range unless another one follows right after it.
+pub(super) fn remove_from_timeline<T>(events: &mut Vec<(Time, T)>, range: TimeRange, carry: bool) {
+    let length = range.end - range.start;
+    events.sort_by_key(|(time, _)| *time);
+    let last_removed = events
+        .iter()
+        .rposition(|(time, _)| range.contains(time))
+        .filter(|_| carry && !events.iter().any(|(time, _)| *time == range.end));
+    let mut index = 0;
+    events.retain(|(time, _)| {
+        index += 1;
+        !range.contains(time) || Some(index - 1) == last_removed
+    });
+    for (time, _) in events.iter_mut() {
+        if *time >= range.end {
+            *time -= length;
+        }
+        else if range.contains(time) {
+            *time = range.start;
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use super::Part;
+use crate::note::articulation::Velocity;
+use crate::note::rhythm::{Duration, Time, TimeGrid};
+use crate::note::Note;
+
+/// Small xorshift generator, so the same seed always humanizes the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 { (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 }
+    /// Roughly normal distributed around 0 with a standard deviation of 1.
+    pub fn next_normal(&mut self) -> f64 {
+        // Irwin-Hall, good enough for timing jitter and never far out
+        (0..12).map(|_| self.next_f64()).sum::<f64>() - 6.0
+    }
+}
+
+/// Random deviations in timing and velocity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Humanize {
+    /// Standard deviation of the timing.
+    pub timing:   Duration,
+    /// Standard deviation of the velocity in midi steps.
+    pub velocity: f32,
+    pub seed:     u64,
+}
+
+impl Default for Humanize {
+    fn default() -> Self {
+        Humanize {
+            timing:   Duration::THIRTY_SECOND / 8,
+            velocity: 6.0,
+            seed:     0,
+        }
+    }
+}
+
+impl Humanize {
+    /// Notes are never moved before `Time::ZERO` and keep their duration.
+    pub fn apply(&self, notes: &mut [Note]) {
+        let mut rng = Rng::new(self.seed);
+        for note in notes {
+            let offset = self.timing * rng.next_normal();
+            note.time = (note.time + offset).max(Time::ZERO);
+            let velocity = note.velocity.0 as f64 + self.velocity as f64 * rng.next_normal();
+            note.velocity = Velocity(velocity.round().clamp(1.0, 127.0) as u8);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GrooveStep {
+    /// How far notes on this step are played off the grid.
+    pub timing:   Duration,
+    /// Factor for the velocity of notes on this step.
+    pub velocity: f32,
+}
+
+impl Default for GrooveStep {
+    fn default() -> Self {
+        GrooveStep {
+            timing:   Duration::default(),
+            velocity: 1.0,
+        }
+    }
+}
+
+/// Timing and velocity offsets per position of a grid, repeating every `steps.len()` lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Groove {
+    pub grid:  TimeGrid,
+    pub steps: Vec<GrooveStep>,
+}
+
+impl Groove {
+    /// Averages how the notes of `part` deviate from `grid`, with a cycle of `length` grid
+    /// lines, e.g. 8 sixteenths for a two beat groove.
+    pub fn extract(part: &Part, grid: TimeGrid, length: usize) -> Groove {
+        let length = length.max(1);
+        let mut sums = vec![(0i64, 0f64, 0usize); length];
+        let mut velocity_sum = 0.0;
+        for note in &part.notes {
+            let (i, line) = grid.nearest(note.time);
+            let sum = &mut sums[i.rem_euclid(length as i64) as usize];
+            sum.0 += (note.time - line).0;
+            sum.1 += note.velocity.0 as f64;
+            sum.2 += 1;
+            velocity_sum += note.velocity.0 as f64;
+        }
+        let average_velocity = velocity_sum / part.notes.len().max(1) as f64;
+
+        let steps = sums
+            .into_iter()
+            .map(|(timing, velocity, count)| match count {
+                0 => GrooveStep::default(),
+                _ => GrooveStep {
+                    timing:   Duration(timing / count as i64),
+                    velocity: (velocity / count as f64 / average_velocity) as f32,
+                },
+            })
+            .collect();
+        Groove { grid, steps }
+    }
+
+    /// Moves the notes by the offsets of their nearest grid line. `strength` of 1 applies the
+    /// full groove, 0 leaves the notes alone.
+    pub fn apply(&self, notes: &mut [Note], strength: f32) {
+        if self.steps.is_empty() {
+            return;
+        }
+        for note in notes {
+            let (i, _) = self.grid.nearest(note.time);
+            let step = self.steps[i.rem_euclid(self.steps.len() as i64) as usize];
+            note.time = (note.time + step.timing * strength).max(Time::ZERO);
+            let factor = 1.0 + (step.velocity - 1.0) * strength;
+            let velocity = note.velocity.0 as f32 * factor;
+            note.velocity = Velocity(velocity.round().clamp(1.0, 127.0) as u8);
+        }
+    }
+}
+
+/// Swing feel, played by delaying every second `level` note of each pair.
+///
+/// Times inside each pair are remapped linearly, so `straighten` exactly undoes `apply`
+/// (up to rounding) and can be used to notate swung performances as straight notes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Swing {
+    /// Length of the first note of a pair relative to the second. 1 is straight, 2 is the
+    /// triplet feel, 3 a dotted shuffle.
+    pub ratio: f32,
+    /// `Duration::EIGHTH` or `Duration::SIXTEENTH` swing.
+    pub level: Duration,
+}
+
+impl Default for Swing {
+    fn default() -> Self {
+        Swing {
+            ratio: 2.0,
+            level: Duration::EIGHTH,
+        }
+    }
+}
+
+impl Swing {
+    pub fn new(ratio: f32, level: Duration) -> Self { Swing { ratio, level } }
+
+    /// Grid of the pairs, starting at `start`, usually a bar line.
+    pub fn grid(&self, start: Time) -> TimeGrid { TimeGrid::new(start, self.level * 2) }
+
+    pub fn swing_time(&self, time: Time, grid: TimeGrid) -> Time {
+        self.remap(time, grid, self.level, self.offbeat())
+    }
+    pub fn straighten_time(&self, time: Time, grid: TimeGrid) -> Time {
+        self.remap(time, grid, self.offbeat(), self.level)
+    }
+
+    pub fn apply(&self, notes: &mut [Note], grid: TimeGrid) {
+        for note in notes {
+            let end = self.swing_time(note.time + note.duration, grid);
+            note.time = self.swing_time(note.time, grid);
+            note.duration = end - note.time;
+        }
+    }
+    pub fn straighten(&self, notes: &mut [Note], grid: TimeGrid) {
+        for note in notes {
+            let end = self.straighten_time(note.time + note.duration, grid);
+            note.time = self.straighten_time(note.time, grid);
+            note.duration = end - note.time;
+        }
+    }
+
+    /// Swings the part with the pairs starting at each time signature change.
+    pub fn apply_part(&self, part: &mut Part) {
+        self.for_sections(part, |swing, notes, grid| swing.apply(notes, grid));
+    }
+    pub fn straighten_part(&self, part: &mut Part) {
+        self.for_sections(part, |swing, notes, grid| swing.straighten(notes, grid));
+    }
+
+    /// Where the second note of a pair lands when swung.
+    fn offbeat(&self) -> Duration {
+        let ratio = self.ratio.max(0.0) as f64;
+        self.level * (2.0 * ratio / (1.0 + ratio))
+    }
+
+    /// Maps the position `from` inside each pair to `to`, scaling both halves linearly.
+    fn remap(&self, time: Time, grid: TimeGrid, from: Duration, to: Duration) -> Time {
+        let pair = self.level * 2;
+        if pair <= Duration::default() || from <= Duration::default() || from >= pair {
+            return time;
+        }
+        let index = (time - grid.start).0.div_euclid(pair.0);
+        let start = grid.start + pair * index;
+        let position = (time - start).0 as f64;
+        let (from, to, pair) = (from.0 as f64, to.0 as f64, pair.0 as f64);
+        let mapped = if position < from {
+            position * to / from
+        }
+        else {
+            to + (position - from) * (pair - to) / (pair - from)
+        };
+        start + Duration(mapped.round() as i64)
+    }
+
+    fn for_sections(&self, part: &mut Part, f: impl Fn(&Self, &mut [Note], TimeGrid)) {
+        let mut starts = part
+            .time_signature
+            .iter()
+            .map(|(time, _)| *time)
+            .collect::<Vec<_>>();
+        starts.push(Time::ZERO);
+        starts.sort();
+        starts.dedup();
+
+        for note in &mut part.notes {
+            let section = starts.partition_point(|start| *start <= note.time).max(1) - 1;
+            f(self, std::slice::from_mut(note), self.grid(starts[section]));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Part, Score};
+use crate::note::{Note, NoteId};
+
+impl Part {
+    /// Gives every note without an id, or with the id of an earlier note, a new one.
+    pub fn assign_ids(&mut self) {
+        let mut next = self.next_note_id().0;
+        let mut seen = BTreeSet::new();
+        for note in &mut self.notes {
+            if note.id == NoteId::UNASSIGNED || !seen.insert(note.id) {
+                note.id = NoteId(next);
+                seen.insert(note.id);
+                next += 1;
+            }
+        }
+        self.next_id = next;
+    }
+    /// An id no note of the part has or had.
+    pub fn next_note_id(&self) -> NoteId {
+        let last = self.notes.iter().map(|n| n.id.0).max().unwrap_or(0);
+        NoteId(self.next_id.max(last + 1))
+    }
+    /// Keeps `id` from being handed out again.
+    pub(crate) fn reserve_id(&mut self, id: NoteId) { self.next_id = self.next_id.max(id.0 + 1) }
+    /// Whether every note has an id of its own.
+    pub fn has_unique_ids(&self) -> bool {
+        let mut seen = BTreeSet::new();
+        self.notes
+            .iter()
+            .all(|n| n.id != NoteId::UNASSIGNED && seen.insert(n.id))
+    }
+
+    pub fn note_index(&self, id: NoteId) -> Option<usize> {
+        if id == NoteId::UNASSIGNED {
+            return None;
+        }
+        self.notes.iter().position(|note| note.id == id)
+    }
+    /// The index of every note by id, for looking up many at once.
+    pub fn note_indices(&self) -> BTreeMap<NoteId, usize> {
+        let mut indices = BTreeMap::new();
+        // Like `note_index`, the first note wins
+        for (index, note) in self.notes.iter().enumerate().rev() {
+            if note.id != NoteId::UNASSIGNED {
+                indices.insert(note.id, index);
+            }
+        }
+        indices
+    }
+    pub fn note(&self, id: NoteId) -> Option<&Note> { Some(&self.notes[self.note_index(id)?]) }
+    pub fn note_mut(&mut self, id: NoteId) -> Option<&mut Note> {
+        let index = self.note_index(id)?;
+        Some(&mut self.notes[index])
+    }
+
+    /// Inserts the note after the notes starting before or with it. It keeps its id unless it
+    /// has none or the id is taken.
+    pub fn add_note(&mut self, mut note: Note) -> NoteId {
+        if note.id == NoteId::UNASSIGNED || self.note_index(note.id).is_some() {
+            note.id = self.next_note_id();
+        }
+        let id = note.id;
+        self.reserve_id(id);
+        let index = self.notes.partition_point(|n| n.time <= note.time);
+        self.notes.insert(index, note);
+        id
+    }
+    /// Removes the note along with its lyrics and the spanners attached to it.
+    pub fn remove_note(&mut self, id: NoteId) -> Option<Note> {
+        let note = self.notes.remove(self.note_index(id)?);
+        self.reserve_id(id);
+        self.lyrics.retain(|lyric| lyric.note != id);
+        self.spanners
+            .retain(|spanner| spanner.start != id && spanner.end != id);
+        Some(note)
+    }
+}
+
+impl Score {
+    /// Gives the notes of every part ids, e.g. after loading a score saved without them.
+    pub fn assign_ids(&mut self) { self.parts.iter_mut().for_each(Part::assign_ids) }
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use super::edit::Edit;
+use super::Part;
+use crate::note::harmony::PitchRange;
+use crate::note::rhythm::{Time, TimeRange};
+use crate::note::{Note, NoteId};
+
+/// Notes per chunk before it is split in two.
+const CHUNK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Default)]
+struct Chunk {
+    /// Sorted by start.
+    notes:   Vec<Note>,
+    /// Indices of the notes sorted by end, to find the ones still sounding after a time
+    /// without going through the ones that ended.
+    by_end:  Vec<usize>,
+    /// Latest end of any note in the chunk, to skip chunks that ended before a query.
+    max_end: Time,
+}
+
+impl Chunk {
+    fn new(notes: Vec<Note>) -> Self {
+        let mut chunk = Chunk {
+            notes,
+            ..Default::default()
+        };
+        chunk.update_ends();
+        chunk
+    }
+    fn update_ends(&mut self) {
+        let end = |i: usize| self.notes[i].time + self.notes[i].duration;
+        let mut by_end = (0..self.notes.len()).collect::<Vec<_>>();
+        by_end.sort_by_key(|i| end(*i));
+        self.max_end = by_end.last().map_or(Time::ZERO, |i| end(*i));
+        self.by_end = by_end;
+    }
+    fn first_start(&self) -> Time { self.notes.first().map_or(Time::ZERO, |n| n.time) }
+    fn last_start(&self) -> Time { self.notes.last().map_or(Time::ZERO, |n| n.time) }
+    /// Indices of the notes before `before` that end after `time`, sorted.
+    fn ending_after(&self, time: Time, before: usize) -> Vec<usize> {
+        if before == 0 {
+            return Vec::new();
+        }
+        let first = self.by_end.partition_point(|i| {
+            let note = &self.notes[*i];
+            note.time + note.duration <= time
+        });
+        let mut indices = self.by_end[first..]
+            .iter()
+            .copied()
+            .filter(|i| *i < before)
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+/// Notes sorted by start in chunks that also sort them by end, for fast range queries on large
+/// scores. A query goes through the notes sounding in the range and the notes of the chunk it
+/// starts in, however long the notes are. Inserting, removing and updating only touch one
+/// chunk.
+///
+/// The index is a copy of the notes of a part owned by whoever queries it, which applies the
+/// edits of the part with `apply` or builds it again from the part.
+///
+/// Every note gets an id when it is inserted without one.
+#[derive(Debug, Clone, Default)]
+pub struct NoteIndex {
+    chunks:  Vec<Chunk>,
+    /// Start of every note, to find its chunk.
+    starts:  BTreeMap<NoteId, Time>,
+    next_id: u64,
+}
+
+impl NoteIndex {
+    pub fn new() -> Self { NoteIndex::default() }
+
+    pub fn len(&self) -> usize { self.starts.len() }
+    pub fn is_empty(&self) -> bool { self.starts.is_empty() }
+    /// All notes sorted by start.
+    pub fn iter(&self) -> impl Iterator<Item = &Note> + '_ {
+        self.chunks.iter().flat_map(|chunk| &chunk.notes)
+    }
+    pub fn into_notes(self) -> Vec<Note> {
+        self.chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.notes)
+            .collect()
+    }
+
+    pub fn get(&self, id: NoteId) -> Option<&Note> {
+        let (chunk, index) = self.position(id)?;
+        Some(&self.chunks[chunk].notes[index])
+    }
+
+    /// Adds a note, giving it a new id if it has none or one that is already used.
+    pub fn insert(&mut self, mut note: Note) -> NoteId {
+        if note.id == NoteId::UNASSIGNED || self.starts.contains_key(&note.id) {
+            note.id = NoteId(self.next_id.max(1));
+        }
+        self.next_id = self.next_id.max(note.id.0 + 1);
+        self.starts.insert(note.id, note.time);
+        let id = note.id;
+
+        if self.chunks.is_empty() {
+            self.chunks.push(Chunk::default());
+        }
+        let chunk = self
+            .chunks
+            .partition_point(|chunk| chunk.last_start() <= note.time)
+            .min(self.chunks.len() - 1);
+        let target = &mut self.chunks[chunk];
+        let index = target.notes.partition_point(|n| n.time <= note.time);
+        target.notes.insert(index, note);
+
+        if target.notes.len() > CHUNK_SIZE * 2 {
+            let second = target.notes.split_off(CHUNK_SIZE);
+            self.chunks.insert(chunk + 1, Chunk::new(second));
+        }
+        self.chunks[chunk].update_ends();
+        id
+    }
+
+    pub fn remove(&mut self, id: NoteId) -> Option<Note> {
+        let (chunk, index) = self.position(id)?;
+        self.starts.remove(&id);
+        let target = &mut self.chunks[chunk];
+        let note = target.notes.remove(index);
+        if target.notes.is_empty() {
+            self.chunks.remove(chunk);
+        }
+        else {
+            target.update_ends();
+        }
+        Some(note)
+    }
+
+    /// Replaces the note with the same id, returning the old one.
+    pub fn update(&mut self, note: Note) -> Option<Note> {
+        let old = self.remove(note.id)?;
+        self.insert(note);
+        Some(old)
+    }
+    /// Applies the note changes of an edit of the score to the index of `part`. Undoing takes
+    /// the inverse of the edit.
+    pub fn apply(&mut self, part: usize, edit: &Edit) {
+        match edit {
+            Edit::InsertNote { part: p, note, .. } if *p == part => {
+                self.insert(note.clone());
+            }
+            Edit::DeleteNote { part: p, note, .. } if *p == part => {
+                self.remove(note.id);
+            }
+            Edit::UpdateNote {
+                part: p, old, new, ..
+            } if *p == part => {
+                self.remove(old.id);
+                self.insert(new.clone());
+            }
+            Edit::SetScore { new, .. } => {
+                *self = new
+                    .parts
+                    .get(part)
+                    .map_or_else(NoteIndex::new, NoteIndex::from);
+            }
+            Edit::Batch(edits) => edits.iter().for_each(|edit| self.apply(part, edit)),
+            _ => {}
+        }
+    }
+
+    /// Notes sounding at some point in `range`, including notes without duration starting in it.
+    pub fn notes_in(&self, range: TimeRange) -> impl Iterator<Item = &Note> + '_ {
+        self.chunks
+            .iter()
+            .take_while(move |chunk| chunk.first_start() < range.end)
+            .filter(move |chunk| chunk.max_end > range.start || chunk.last_start() >= range.start)
+            .flat_map(move |chunk| {
+                // Notes starting before the range that still sound, then the ones starting in it
+                let first = chunk.notes.partition_point(|n| n.time < range.start);
+                let last = chunk.notes.partition_point(|n| n.time < range.end);
+                chunk
+                    .ending_after(range.start, first)
+                    .into_iter()
+                    .chain(first..last)
+                    .map(|i| &chunk.notes[i])
+            })
+    }
+    pub fn notes_sounding_at(&self, time: Time) -> impl Iterator<Item = &Note> + '_ {
+        self.chunks
+            .iter()
+            .take_while(move |chunk| chunk.first_start() <= time)
+            .filter(move |chunk| chunk.max_end > time)
+            .flat_map(move |chunk| {
+                let last = chunk.notes.partition_point(|n| n.time <= time);
+                chunk
+                    .ending_after(time, last)
+                    .into_iter()
+                    .map(|i| &chunk.notes[i])
+            })
+    }
+    /// Notes sounding in `range` with a pitch in `pitches`, e.g. the visible part of a piano
+    /// roll.
+    pub fn notes_in_area(
+        &self,
+        range: TimeRange,
+        pitches: PitchRange,
+    ) -> impl Iterator<Item = &Note> + '_ {
+        self.notes_in(range)
+            .filter(move |n| pitches.start <= n.pitch && n.pitch < pitches.end)
+    }
+    /// Notes starting in `range`.
+    pub fn notes_starting_in(&self, range: TimeRange) -> impl Iterator<Item = &Note> + '_ {
+        let first = self
+            .chunks
+            .partition_point(|chunk| chunk.last_start() < range.start);
+        self.chunks[first..]
+            .iter()
+            .flat_map(|chunk| &chunk.notes)
+            .skip_while(move |n| n.time < range.start)
+            .take_while(move |n| n.time < range.end)
+    }
+
+    /// Chunk and index in it of the note with `id`.
+    fn position(&self, id: NoteId) -> Option<(usize, usize)> {
+        let time = *self.starts.get(&id)?;
+        let first = self
+            .chunks
+            .partition_point(|chunk| chunk.last_start() < time);
+        self.chunks[first..]
+            .iter()
+            .take_while(|chunk| chunk.first_start() <= time)
+            .enumerate()
+            .find_map(|(offset, chunk)| {
+                let start = chunk.notes.partition_point(|n| n.time < time);
+                let index = chunk.notes[start..]
+                    .iter()
+                    .take_while(|n| n.time == time)
+                    .position(|n| n.id == id)?;
+                Some((first + offset, start + index))
+            })
+    }
+}
+
+impl FromIterator<Note> for NoteIndex {
+    fn from_iter<T: IntoIterator<Item = Note>>(notes: T) -> Self {
+        let mut notes = notes.into_iter().collect::<Vec<_>>();
+        notes.sort_by_key(|n| n.time);
+        let mut index = NoteIndex::new();
+        // Fill chunks directly instead of inserting one by one
+        let mut next_id = notes.iter().map(|n| n.id.0).max().unwrap_or(0) + 1;
+        for note in &mut notes {
+            if note.id == NoteId::UNASSIGNED || index.starts.contains_key(&note.id) {
+                note.id = NoteId(next_id);
+                next_id += 1;
+            }
+            index.starts.insert(note.id, note.time);
+        }
+        index.next_id = next_id;
+        let mut notes = notes.into_iter().peekable();
+        while notes.peek().is_some() {
+            let chunk = notes.by_ref().take(CHUNK_SIZE).collect();
+            index.chunks.push(Chunk::new(chunk));
+        }
+        index
+    }
+}
+
+impl From<&Part> for NoteIndex {
+    fn from(part: &Part) -> Self { part.notes.iter().cloned().collect() }
+}
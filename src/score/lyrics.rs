@@ -0,0 +1,410 @@
+use super::{Part, Score};
+use crate::note::rhythm::{Duration, Time};
+use crate::note::NoteId;
+
+/// Where a syllable sits in its word, as in MusicXML.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Syllabic {
+    /// A whole word.
+    #[default]
+    Single,
+    Begin,
+    Middle,
+    End,
+}
+
+impl Syllabic {
+    /// Whether the word goes on after this syllable, drawn as a hyphen.
+    pub fn continues(&self) -> bool { matches!(self, Syllabic::Begin | Syllabic::Middle) }
+    fn from_continuation(after_hyphen: bool, continues: bool) -> Self {
+        match (after_hyphen, continues) {
+            (false, false) => Syllabic::Single,
+            (false, true) => Syllabic::Begin,
+            (true, true) => Syllabic::Middle,
+            (true, false) => Syllabic::End,
+        }
+    }
+
+    /// The content of the MusicXML `<syllabic>` element.
+    pub fn musicxml(&self) -> &'static str {
+        match self {
+            Syllabic::Single => "single",
+            Syllabic::Begin => "begin",
+            Syllabic::Middle => "middle",
+            Syllabic::End => "end",
+        }
+    }
+    pub fn from_musicxml(text: &str) -> Option<Self> {
+        Some(match text.trim() {
+            "single" => Syllabic::Single,
+            "begin" => Syllabic::Begin,
+            "middle" => Syllabic::Middle,
+            "end" => Syllabic::End,
+            _ => return None,
+        })
+    }
+}
+
+/// A syllable sung on a note.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lyric {
+    pub note:     NoteId,
+    /// Verse number starting at 0, drawn as separate lines.
+    pub verse:    u8,
+    pub text:     String,
+    pub syllabic: Syllabic,
+    /// Melisma, the syllable is held over the following notes up to the next syllable.
+    pub extend:   bool,
+}
+
+impl Lyric {
+    pub fn new(note: NoteId, text: impl Into<String>) -> Self {
+        Lyric {
+            note,
+            verse: 0,
+            text: text.into(),
+            syllabic: Syllabic::Single,
+            extend: false,
+        }
+    }
+    pub fn with_verse(mut self, verse: u8) -> Self {
+        self.verse = verse;
+        self
+    }
+    pub fn with_syllabic(mut self, syllabic: Syllabic) -> Self {
+        self.syllabic = syllabic;
+        self
+    }
+    pub fn with_extend(mut self, extend: bool) -> Self {
+        self.extend = extend;
+        self
+    }
+
+    /// The MusicXML `<lyric>` element for the note.
+    pub fn musicxml(&self) -> String {
+        let text = escape(&self.text);
+        format!(
+            "<lyric number=\"{}\"><syllabic>{}</syllabic><text>{}</text>{}</lyric>",
+            self.verse as u32 + 1,
+            self.syllabic.musicxml(),
+            text,
+            if self.extend { "<extend/>" } else { "" }
+        )
+    }
+
+    /// Reads a MusicXML `<lyric>` element. Several `<text>` elements joined by elisions are
+    /// joined with a space.
+    pub fn from_musicxml(note: NoteId, xml: &str) -> Option<Self> {
+        let (start_tag, content) = element(xml, "lyric")?;
+        let verse = attribute(start_tag, "number")
+            .and_then(|number| number.trim().parse::<u8>().ok())
+            .map_or(0, |number| number.saturating_sub(1));
+        let mut texts = Vec::new();
+        let mut rest = content;
+        while let Some(start) = find_tag(rest, "text") {
+            let Some((_, text)) = element(&rest[start..], "text")
+            else {
+                break;
+            };
+            texts.push(unescape(text));
+            rest = &rest[start + 1..];
+        }
+        if texts.is_empty() {
+            return None;
+        }
+        let syllabic = element(content, "syllabic")
+            .and_then(|(_, syllabic)| Syllabic::from_musicxml(syllabic))
+            .unwrap_or_default();
+        let extend = find_tag(content, "extend").is_some_and(|start| {
+            let tag = &content[start..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            attribute(tag, "type") != Some("stop")
+        });
+        Some(Lyric {
+            note,
+            verse,
+            text: texts.join(" "),
+            syllabic,
+            extend,
+        })
+    }
+
+    /// The text of a MIDI lyric event in the usual karaoke style: syllables that continue the
+    /// word end with a hyphen, words end with a space.
+    pub fn midi_text(&self) -> String {
+        match self.syllabic.continues() {
+            true => format!("{}-", self.text),
+            false => format!("{} ", self.text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextKind {
+    /// Free text such as expressions or performance directions.
+    Text,
+    /// Named position, e.g. "Verse" or "Chorus".
+    Marker,
+    /// Cue for a player or for synchronising with video.
+    CuePoint,
+}
+
+impl TextKind {
+    /// The type byte of the MIDI meta event.
+    pub fn midi_meta_type(&self) -> u8 {
+        match self {
+            TextKind::Text => 0x01,
+            TextKind::Marker => 0x06,
+            TextKind::CuePoint => 0x07,
+        }
+    }
+    /// The MusicXML direction type it is written as.
+    pub fn musicxml_element(&self) -> &'static str {
+        match self {
+            TextKind::Text | TextKind::CuePoint => "words",
+            TextKind::Marker => "rehearsal",
+        }
+    }
+}
+
+/// Text on the score timeline.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextEvent {
+    pub time: Time,
+    pub kind: TextKind,
+    pub text: String,
+}
+
+impl TextEvent {
+    pub fn new(time: Time, kind: TextKind, text: impl Into<String>) -> Self {
+        TextEvent {
+            time,
+            kind,
+            text: text.into(),
+        }
+    }
+}
+
+impl Part {
+    /// The lyrics of one verse with the index of their note, sorted by time. Lyrics on deleted
+    /// notes are skipped.
+    pub fn verse(&self, verse: u8) -> Vec<(usize, &Lyric)> {
+        let mut lyrics = self
+            .lyrics
+            .iter()
+            .filter(|lyric| lyric.verse == verse)
+            .filter_map(|lyric| Some((self.note_index(lyric.note)?, lyric)))
+            .collect::<Vec<_>>();
+        lyrics.sort_by_key(|(note, _)| self.notes[*note].time);
+        lyrics
+    }
+    pub fn verses(&self) -> Vec<u8> {
+        let mut verses = self
+            .lyrics
+            .iter()
+            .map(|lyric| lyric.verse)
+            .collect::<Vec<_>>();
+        verses.sort();
+        verses.dedup();
+        verses
+    }
+
+    /// Attaches the text of MIDI lyric events to the notes starting closest to them, the highest
+    /// one of a chord. Hyphens at the end continue the word, line breaks (`/`, `\`, `\r`, `\n`)
+    /// are dropped and a lone `_` or `+` extends the previous syllable.
+    pub fn add_midi_lyrics(&mut self, events: &[(Time, String)], verse: u8) {
+        self.assign_ids();
+        let mut after_hyphen = false;
+        for (time, text) in events {
+            let text = text
+                .trim_start_matches(['/', '\\', '\r', '\n'])
+                .trim_end_matches(['/', '\\', '\r', '\n']);
+            if matches!(text.trim(), "_" | "+") {
+                if let Some(previous) = self.lyrics.iter_mut().rfind(|l| l.verse == verse) {
+                    previous.extend = true;
+                }
+                continue;
+            }
+            let continues = text.ends_with('-');
+            let text = text.trim().trim_end_matches('-');
+            if text.is_empty() {
+                continue;
+            }
+            let note = self.notes.iter().min_by(|a, b| {
+                let distance = |note: &crate::note::Note| (note.time - *time).0.abs();
+                distance(a)
+                    .cmp(&distance(b))
+                    .then(b.pitch.0.total_cmp(&a.pitch.0))
+            });
+            let Some(note) = note
+            else {
+                break;
+            };
+            self.lyrics.push(
+                Lyric::new(note.id, text)
+                    .with_verse(verse)
+                    .with_syllabic(Syllabic::from_continuation(after_hyphen, continues)),
+            );
+            after_hyphen = continues;
+        }
+    }
+    /// Attaches every `<lyric>` element in the MusicXML `<note>` element `xml` to `note`.
+    /// Returns how many were found.
+    pub fn add_musicxml_lyrics(&mut self, note: NoteId, xml: &str) -> usize {
+        let mut found = 0;
+        let mut rest = xml;
+        while let Some(start) = find_tag(rest, "lyric") {
+            if let Some(lyric) = Lyric::from_musicxml(note, &rest[start..]) {
+                self.lyrics.push(lyric);
+                found += 1;
+            }
+            rest = &rest[start + 1..];
+        }
+        found
+    }
+    /// The `<lyric>` elements of a note for its MusicXML `<note>` element, sorted by verse.
+    pub fn musicxml_lyrics(&self, note: NoteId) -> String {
+        let mut lyrics = self
+            .lyrics
+            .iter()
+            .filter(|lyric| lyric.note == note)
+            .collect::<Vec<_>>();
+        lyrics.sort_by_key(|lyric| lyric.verse);
+        lyrics.iter().map(|lyric| lyric.musicxml()).collect()
+    }
+
+    /// Text for MIDI lyric events of one verse, the inverse of `add_midi_lyrics`.
+    pub fn midi_lyrics(&self, verse: u8) -> Vec<(Time, String)> {
+        self.verse(verse)
+            .into_iter()
+            .map(|(note, lyric)| (self.notes[note].time, lyric.midi_text()))
+            .collect()
+    }
+}
+
+impl Score {
+    /// Text events of one kind, sorted by time.
+    pub fn text_events(&self, kind: TextKind) -> Vec<&TextEvent> {
+        let mut events = self
+            .text_events
+            .iter()
+            .filter(|event| event.kind == kind)
+            .collect::<Vec<_>>();
+        events.sort_by_key(|event| event.time);
+        events
+    }
+    /// The marker in effect at `time`.
+    pub fn marker_at(&self, time: Time) -> Option<&TextEvent> {
+        self.text_events(TextKind::Marker)
+            .into_iter()
+            .rfind(|event| event.time <= time)
+    }
+    /// The part lyrics from a track without notes belong to: the one with the most notes
+    /// starting with a syllable, the higher one if that's a tie. Drums are never picked.
+    pub fn melody_part(&self, lyrics: &[(Time, String)]) -> Option<usize> {
+        let tolerance = Duration::THIRTY_SECOND;
+        self.parts
+            .iter()
+            .enumerate()
+            .filter(|(_, part)| {
+                !part.notes.is_empty()
+                    && !part.instrument.as_ref().is_some_and(|i| i.is_percussion())
+            })
+            .map(|(i, part)| {
+                let mut starts = part.notes.iter().map(|n| n.time).collect::<Vec<_>>();
+                starts.sort();
+                let matches = lyrics
+                    .iter()
+                    .filter(|(time, _)| {
+                        let next = starts.partition_point(|start| *start < *time - tolerance);
+                        starts
+                            .get(next)
+                            .is_some_and(|start| *start <= *time + tolerance)
+                    })
+                    .count();
+                let pitch =
+                    part.notes.iter().map(|n| n.pitch.0).sum::<f32>() / part.notes.len() as f32;
+                (i, matches, pitch)
+            })
+            .max_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)))
+            .map(|(i, ..)| i)
+    }
+
+    /// Text events as MIDI meta events: the type byte and the text.
+    pub fn midi_text_events(&self) -> Vec<(Time, u8, String)> {
+        let mut events = self
+            .text_events
+            .iter()
+            .map(|event| (event.time, event.kind.midi_meta_type(), event.text.clone()))
+            .collect::<Vec<_>>();
+        events.sort();
+        events
+    }
+}
+
+/// Index of the first `<name ...>` or `<name>` start tag.
+fn find_tag(xml: &str, name: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(offset) = xml[from..].find('<') {
+        let start = from + offset;
+        let after = &xml[start + 1..];
+        if after.starts_with(name)
+            && after[name.len()..].starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        {
+            return Some(start);
+        }
+        from = start + 1;
+    }
+    None
+}
+
+/// The start tag and the content of the first `name` element, empty for `<name/>`.
+fn element<'a>(xml: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    let start = find_tag(xml, name)?;
+    let tag_end = start + xml[start..].find('>')?;
+    let start_tag = &xml[start..tag_end];
+    if start_tag.ends_with('/') {
+        return Some((start_tag, ""));
+    }
+    let content = &xml[tag_end + 1..];
+    let end = content.find(&format!("</{name}"))?;
+    Some((start_tag, &content[..end]))
+}
+
+fn attribute<'a>(start_tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut from = 0;
+    while let Some(offset) = start_tag[from..].find(name) {
+        let at = from + offset;
+        from = at + name.len();
+        let preceded = start_tag[..at].ends_with(char::is_whitespace);
+        let Some(value) = start_tag[from..].trim_start().strip_prefix('=')
+        else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        if preceded {
+            return value.find(quote).map(|end| &value[..end]);
+        }
+    }
+    None
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,206 @@
+use super::{bar_length, beat_length, Part, Score};
+use crate::instrument::Instrument;
+use crate::note::articulation::Velocity;
+use crate::note::harmony::Pitch;
+use crate::note::rhythm::{Duration, Time, TimeSignature};
+use crate::note::Note;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClickKind {
+    /// The first beat of a bar.
+    Downbeat,
+    Beat,
+    /// A click between two beats.
+    Subdivision,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Click {
+    pub time: Time,
+    pub kind: ClickKind,
+    /// Index of the bar, negative for bars of the count-in.
+    pub bar:  i64,
+    /// Beat inside the bar, counting from 0.
+    pub beat: u32,
+}
+
+/// Generates click tracks from the time signatures of a part.
+///
+/// Compound meters (6/8, 9/8, 12/8, ...) click on the dotted beats, the eighths in between are
+/// subdivisions.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metronome {
+    pub accent_downbeat: bool,
+    /// Clicks per beat, 1 only clicks the beats. Ignored for compound meters, which are
+    /// already subdivided.
+    pub subdivisions: u8,
+    /// Whether compound meters click every eighth.
+    pub compound_subdivided: bool,
+    /// Bars of clicks before the first bar.
+    pub count_in: u32,
+    /// General MIDI percussion keys.
+    pub downbeat_key: u8,
+    pub beat_key: u8,
+    pub subdivision_key: u8,
+    pub downbeat_velocity: Velocity,
+    pub beat_velocity: Velocity,
+    pub subdivision_velocity: Velocity,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Metronome {
+            accent_downbeat: true,
+            subdivisions: 1,
+            compound_subdivided: false,
+            count_in: 0,
+            // Hi and Low Wood Block
+            downbeat_key: 76,
+            beat_key: 77,
+            subdivision_key: 77,
+            downbeat_velocity: Velocity(120),
+            beat_velocity: Velocity(90),
+            subdivision_velocity: Velocity(60),
+        }
+    }
+}
+
+impl Metronome {
+    pub fn with_subdivisions(self, subdivisions: u8) -> Self {
+        Metronome {
+            subdivisions: subdivisions.max(1),
+            ..self
+        }
+    }
+    pub fn with_count_in(self, bars: u32) -> Self {
+        Metronome {
+            count_in: bars,
+            ..self
+        }
+    }
+
+    /// All clicks of `part` up to `end`, without the count-in.
+    pub fn clicks(&self, part: &Part, end: Time) -> Vec<Click> {
+        let mut clicks = Vec::new();
+        for (bar, (start, signature)) in part.bars_until(end).enumerate() {
+            self.bar_clicks(signature, start, bar as i64, &mut clicks);
+        }
+        clicks.retain(|click| click.time < end);
+        clicks
+    }
+
+    /// Clicks of the count-in, starting at `Time::ZERO`, and its length. Uses the first time
+    /// signature of `part`.
+    pub fn count_in_clicks(&self, part: &Part) -> (Vec<Click>, Duration) {
+        let signature = part
+            .time_signature
+            .iter()
+            .min_by_key(|(time, _)| *time)
+            .map_or_else(TimeSignature::default, |(_, signature)| *signature);
+        let mut clicks = Vec::new();
+        for bar in 0..self.count_in as i64 {
+            self.bar_clicks(
+                signature,
+                Time::ZERO + bar_length(signature) * bar,
+                bar - self.count_in as i64,
+                &mut clicks,
+            );
+        }
+        (clicks, bar_length(signature) * self.count_in as i64)
+    }
+
+    /// The clicks as notes of a percussion part.
+    pub fn to_part(&self, clicks: &[Click]) -> Part {
+        let mut part = Part {
+            description: "Metronome".to_string(),
+            instrument: Some(Instrument::drum_kit()),
+            notes: clicks.iter().map(|click| self.to_note(click)).collect(),
+            ..Default::default()
+        };
+        part.assign_ids();
+        part
+    }
+    pub fn to_note(&self, click: &Click) -> Note {
+        let (key, velocity) = match click.kind {
+            ClickKind::Downbeat if self.accent_downbeat => {
+                (self.downbeat_key, self.downbeat_velocity)
+            }
+            ClickKind::Downbeat | ClickKind::Beat => (self.beat_key, self.beat_velocity),
+            ClickKind::Subdivision => (self.subdivision_key, self.subdivision_velocity),
+        };
+        Note {
+            time: click.time,
+            duration: Duration::SIXTEENTH,
+            pitch: Pitch::from_midi(key as i32),
+            velocity,
+            ..Default::default()
+        }
+    }
+
+    /// A copy of `score` with a click track for `part` appended as the last part. With a
+    /// count-in everything is moved back by its length, the count-in plays at the initial tempo.
+    pub fn apply(&self, score: &Score, part: usize) -> Score {
+        let Some(source) = score.parts.get(part)
+        else {
+            return score.clone();
+        };
+        let end = score
+            .parts
+            .iter()
+            .flat_map(|part| part.notes.iter())
+            .map(|note| note.time + note.duration)
+            .max()
+            .unwrap_or(Time::ZERO);
+
+        let (mut clicks, offset) = self.count_in_clicks(source);
+        let mut score = score.shifted(offset);
+        clicks.extend(self.clicks(source, end).into_iter().map(|click| Click {
+            time: click.time + offset,
+            ..click
+        }));
+        score.parts.push(self.to_part(&clicks));
+        score
+    }
+
+    fn bar_clicks(&self, signature: TimeSignature, start: Time, bar: i64, out: &mut Vec<Click>) {
+        let compound = is_compound(signature);
+        let (beats, step, subdivisions) = if compound {
+            let subdivisions = if self.compound_subdivided { 3 } else { 1 };
+            (
+                signature.numerator as i64 / 3,
+                beat_length(signature) * 3,
+                subdivisions,
+            )
+        }
+        else {
+            (
+                signature.numerator as i64,
+                beat_length(signature),
+                self.subdivisions.max(1) as i64,
+            )
+        };
+
+        for beat in 0..beats {
+            let beat_start = start + step * beat;
+            for sub in 0..subdivisions {
+                let kind = match (beat, sub) {
+                    (0, 0) => ClickKind::Downbeat,
+                    (_, 0) => ClickKind::Beat,
+                    _ => ClickKind::Subdivision,
+                };
+                out.push(Click {
+                    time: beat_start + step * sub / subdivisions,
+                    kind,
+                    bar,
+                    beat: beat as u32,
+                });
+            }
+        }
+    }
+}
+
+fn is_compound(signature: TimeSignature) -> bool {
+    signature.subdivision >= 8 && signature.numerator > 3 && signature.numerator.is_multiple_of(3)
+}
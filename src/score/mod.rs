@@ -1,50 +1,216 @@
+pub mod articulations;
+pub mod diff;
+pub mod dynamics;
 pub mod edit;
+pub mod form;
+pub mod groove;
+pub mod ids;
+pub mod index;
+pub mod lyrics;
+pub mod metronome;
 pub mod rendering;
+pub mod sections;
+pub mod selection;
+pub mod spanners;
+pub mod transpose;
+pub mod voices;
 
-use core::str;
 use std::ops::Range;
 
-use crate::note::articulation::Velocity;
-use crate::note::harmony::{KeySignature, Pitch};
-use crate::note::rhythm::{Duration, Tempo, Time, TimeSignature};
+use dynamics::Dynamic;
+use form::FormMarking;
+use lyrics::{Lyric, TextEvent};
+use spanners::Spanner;
+use voices::Rest;
+
+use crate::instrument::{Instrument, PitchDisplay, RangeStatus};
+use crate::note::harmony::KeySignature;
+use crate::note::rhythm::{Duration, Tempo, TempoMap, Time, TimeRange, TimeSignature};
 use crate::note::Note;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Score {
-    pub parts:     Vec<Part>,
+    pub parts: Vec<Part>,
     pub tempo_map: Vec<(Time, Tempo)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub text_events: Vec<TextEvent>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub form: Vec<(Time, FormMarking)>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Part {
     pub description: String,
+    pub instrument: Option<Instrument>,
     pub notes: Vec<Note>,
     pub time_signature: Vec<(Time, TimeSignature)>,
     pub key_signature: Vec<(Time, KeySignature)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rests: Vec<Rest>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub spanners: Vec<Spanner>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dynamics: Vec<(Time, Dynamic)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lyrics: Vec<Lyric>,
+    /// Ids below this have been handed out, so notes never get the id of a deleted note.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub next_id: u64,
 }
 impl Part {
+    /// Start and time signature of every bar up to the end of the last note.
     pub fn bars(&self) -> impl Iterator<Item = (Time, TimeSignature)> + '_ {
-        self.notes
+        let end = self
+            .notes
             .iter()
             .map(|n| n.time + n.duration)
             .max()
             .unwrap_or(Time::ZERO);
+        self.bars_until(end)
+    }
+    /// Start and time signature of every bar starting before `end`, following the time
+    /// signature changes. Parts without any start in 4/4.
+    pub fn bars_until(&self, end: Time) -> impl Iterator<Item = (Time, TimeSignature)> + '_ {
+        let mut changes = self.time_signature.clone();
+        changes.sort_by_key(|(time, _)| *time);
+        if changes.first().is_none_or(|(time, _)| *time > Time::ZERO) {
+            changes.insert(0, (Time::ZERO, TimeSignature::default()));
+        }
+
+        let mut change = 0;
+        let mut time = changes[0].0;
+        std::iter::from_fn(move || {
+            while changes
+                .get(change + 1)
+                .is_some_and(|(next, _)| *next <= time)
+            {
+                // A change in the middle of a bar takes effect with the next one
+                change += 1;
+            }
+            let signature = changes[change].1;
+            let bar = (time, signature);
+            time += bar_length(signature);
+            Some(bar)
+        })
+        .take_while(move |(time, _)| *time < end)
+    }
+    /// Time range of the bar with the given index.
+    pub fn bar_range(&self, index: usize) -> Option<TimeRange> {
+        let (start, signature) = self.bars_until(Time(i64::MAX / 2)).nth(index)?;
+        Some(TimeRange {
+            start,
+            end: start + bar_length(signature),
+        })
+    }
+
+    /// The key signature in effect at `time`, C major if there is none.
+    pub fn key_signature_at(&self, time: Time) -> KeySignature {
+        self.key_signature
+            .iter()
+            .filter(|(start, _)| *start <= time)
+            .max_by_key(|(start, _)| *start)
+            .map(|(_, key)| key.clone())
+            .unwrap_or_default()
+    }
+
+    /// Indices of all notes that aren't comfortably inside the instrument's range.
+    pub fn check_range(&self) -> Vec<(usize, RangeStatus)> {
+        let Some(instrument) = &self.instrument
+        else {
+            return Vec::new();
+        };
+        self.notes
+            .iter()
+            .enumerate()
+            .map(|(i, note)| (i, instrument.range_status(note.pitch)))
+            .filter(|(_, status)| *status != RangeStatus::Comfortable)
+            .collect()
+    }
+
+    /// A copy of this part with the pitches and key signatures as they should be displayed or
+    /// exported. Parts without an instrument are always in concert pitch.
+    pub fn with_pitch_display(&self, display: PitchDisplay) -> Part {
+        let mut part = self.clone();
+        if let Some(instrument) = &self.instrument {
+            for note in &mut part.notes {
+                note.pitch = instrument.display_pitch(note.pitch, display);
+            }
+            for (_, key) in &mut part.key_signature {
+                *key = instrument.display_key_signature(key, display);
+            }
+        }
+        part
+    }
+}
+
+impl Score {
+    pub fn tempo(&self) -> TempoMap { TempoMap::new(&self.tempo_map) }
+    /// End of the last note of any part.
+    pub fn end(&self) -> Time {
+        self.parts
+            .iter()
+            .flat_map(|part| &part.notes)
+            .map(|n| n.time + n.duration)
+            .max()
+            .unwrap_or(Time::ZERO)
+    }
+
+    /// A copy of this score with everything moved later by `offset`. The tempo at the start
+    /// is kept for the gap.
+    pub fn shifted(&self, offset: Duration) -> Score {
+        let mut score = self.clone();
+        for part in &mut score.parts {
+            for note in &mut part.notes {
+                note.time += offset;
+            }
+            for (time, _) in &mut part.time_signature {
+                *time += offset;
+            }
+            for (time, _) in &mut part.key_signature {
+                *time += offset;
+            }
+            for rest in &mut part.rests {
+                rest.time += offset;
+            }
+            for (time, _) in &mut part.dynamics {
+                *time += offset;
+            }
+        }
+        for event in &mut score.text_events {
+            event.time += offset;
+        }
+        for (time, _) in &mut score.form {
+            *time += offset;
+        }
+        if offset != Duration::default() {
+            let initial = self.tempo().tempo_at(Time::ZERO);
+            for (time, _) in &mut score.tempo_map {
+                *time += offset;
+            }
+            score.tempo_map.insert(0, (Time::ZERO, initial));
+        }
+        score
+    }
 
-        return TimeSignature::default()
-            .bars_in(
-                Time::ZERO,
-                self.notes
-                    .iter()
-                    .map(|n| n.time + n.duration)
-                    .max()
-                    .unwrap_or(Time::ZERO),
-            )
-            .map(|t| (t, TimeSignature::default()));
+    pub fn with_pitch_display(&self, display: PitchDisplay) -> Score {
+        let mut score = self.clone();
+        for part in &mut score.parts {
+            *part = part.with_pitch_display(display);
+        }
+        score
     }
 }
 
+/// Length of one beat as written, e.g. an eighth in 6/8.
+pub(crate) fn beat_length(signature: TimeSignature) -> Duration {
+    Duration::WHOLE / signature.subdivision.max(1) as i64
+}
+pub(crate) fn bar_length(signature: TimeSignature) -> Duration {
+    beat_length(signature) * signature.numerator.max(1) as i64
+}
+
 pub struct Bar {
     pub start: Time,
     pub end: Time,
@@ -65,15 +231,24 @@ impl From<midly::Error> for FromMidiError {
 #[cfg(feature = "midly")]
 impl Score {
     pub fn from_midi_data(data: &[u8]) -> Result<Self, FromMidiError> {
+        use core::str;
+
+        use lyrics::TextKind;
         use midly::{MetaMessage, MidiMessage, Timing, TrackEvent, TrackEventKind};
 
+        use crate::instrument::drums::GM_PERCUSSION_CHANNEL;
+        use crate::note::articulation::Velocity;
+        use crate::note::harmony::Pitch;
+
         let (header, tracks) = midly::parse(data)?;
 
         let mut result = Score::default();
 
+        let mut lyric_tracks = Vec::new();
         for track in tracks {
             let mut track_data = Part::default();
             let mut time = Time::ZERO;
+            let mut lyrics = Vec::new();
             let (mut time_numerator, mut time_denomintr) = match header.timing {
                 // Default 120 BPM until we get a tempo event
                 Timing::Timecode(frames_per_second, subframes_per_frame) => (
@@ -90,14 +265,19 @@ impl Score {
 
                 match kind {
                     TrackEventKind::Midi { channel, message } => match message {
-                        MidiMessage::NoteOn { key, vel } => track_data.notes.push(Note {
-                            time,
-                            pitch: Pitch::from_midi(key.as_int() as i32),
-                            velocity: Velocity::from_midi(vel.as_int()),
-                            duration: Duration::SIXTEENTH,
-                            channel: Some(channel.as_int()),
-                            ..Default::default()
-                        }),
+                        MidiMessage::NoteOn { key, vel } => {
+                            if channel.as_int() == GM_PERCUSSION_CHANNEL {
+                                track_data.instrument = Some(Instrument::drum_kit());
+                            }
+                            track_data.notes.push(Note {
+                                time,
+                                pitch: Pitch::from_midi(key.as_int() as i32),
+                                velocity: Velocity::from_midi(vel.as_int()),
+                                duration: Duration::SIXTEENTH,
+                                channel: Some(channel.as_int()),
+                                ..Default::default()
+                            })
+                        }
                         MidiMessage::NoteOff { key, vel: _ } => {
                             // Find the note by pitch and set the duration so it ends *now*
                             let pitch = Pitch::from_midi(key.as_int() as i32);
@@ -111,7 +291,17 @@ impl Score {
                             controller: _,
                             value: _,
                         } => (),
-                        MidiMessage::ProgramChange { program: _ } => (),
+                        MidiMessage::ProgramChange { program } => {
+                            // Program changes on the percussion channel select drum kits
+                            if track_data.instrument.is_none()
+                                && channel.as_int() != GM_PERCUSSION_CHANNEL
+                            {
+                                track_data.instrument = Some(
+                                    Instrument::from_gm_program(program.as_int())
+                                        .with_channel(channel.as_int()),
+                                );
+                            }
+                        }
                         MidiMessage::ChannelAftertouch { vel: _ } => (),
                         MidiMessage::PitchBend { bend: _ } => (),
                     },
@@ -119,7 +309,13 @@ impl Score {
                     TrackEventKind::Escape(_) => (),
                     TrackEventKind::Meta(meta) => match meta {
                         MetaMessage::TrackNumber(_) => (),
-                        MetaMessage::Text(_) => (),
+                        MetaMessage::Text(text) => {
+                            if let Ok(text) = str::from_utf8(text) {
+                                result
+                                    .text_events
+                                    .push(TextEvent::new(time, TextKind::Text, text));
+                            }
+                        }
                         MetaMessage::Copyright(_) => (),
                         MetaMessage::TrackName(name) => {
                             if let Ok(name) = str::from_utf8(name) {
@@ -131,9 +327,29 @@ impl Score {
                                 track_data.description += name;
                             }
                         }
-                        MetaMessage::Lyric(_) => (),
-                        MetaMessage::Marker(_) => (),
-                        MetaMessage::CuePoint(_) => (),
+                        MetaMessage::Lyric(text) => {
+                            if let Ok(text) = str::from_utf8(text) {
+                                lyrics.push((time, text.to_string()));
+                            }
+                        }
+                        MetaMessage::Marker(text) => {
+                            if let Ok(text) = str::from_utf8(text) {
+                                result.text_events.push(TextEvent::new(
+                                    time,
+                                    TextKind::Marker,
+                                    text,
+                                ));
+                            }
+                        }
+                        MetaMessage::CuePoint(text) => {
+                            if let Ok(text) = str::from_utf8(text) {
+                                result.text_events.push(TextEvent::new(
+                                    time,
+                                    TextKind::CuePoint,
+                                    text,
+                                ));
+                            }
+                        }
                         MetaMessage::ProgramName(_) => (),
                         MetaMessage::DeviceName(name) => {
                             if let Ok(name) = str::from_utf8(name) {
@@ -182,8 +398,23 @@ impl Score {
                 }
             }
 
+            track_data.assign_ids();
+            if track_data.notes.is_empty() && !lyrics.is_empty() {
+                // A karaoke track, attached to the melody once all tracks are read
+                lyric_tracks.push(lyrics);
+            }
+            else if !lyrics.is_empty() {
+                track_data.add_midi_lyrics(&lyrics, 0);
+            }
             result.parts.push(track_data);
         }
+        for lyrics in lyric_tracks {
+            if let Some(melody) = result.melody_part(&lyrics) {
+                let part = &mut result.parts[melody];
+                let verse = part.verses().last().map_or(0, |verse| verse + 1);
+                part.add_midi_lyrics(&lyrics, verse);
+            }
+        }
 
         Ok(result)
     }
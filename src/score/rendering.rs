@@ -1,5 +1,10 @@
+use super::Part;
+use crate::instrument::chord_shapes::Voicing;
+use crate::instrument::fretted::FrettedTuning;
+use crate::note::articulation::Finger;
 use crate::note::harmony::{Interval, Pitch, PitchRange};
 use crate::note::rhythm::{Duration, Time, TimeRange};
+use crate::note::Note;
 
 #[derive(Debug, Clone, Copy)]
 pub struct MidiRollViewport {
@@ -140,3 +145,298 @@ impl MidiRoll {
         }
     }
 }
+
+pub struct TabNote {
+    pub x: f32,
+    pub y: f32,
+    pub string: u8,
+    pub fret: u8,
+}
+
+/// Layout for a tab staff. String 0 is drawn as the bottom line.
+#[derive(Debug, Clone, Copy)]
+pub struct Tab {
+    pub rect: Rect,
+    pub time_range: TimeRange,
+    pub strings: u8,
+}
+impl Tab {
+    pub fn new(rect: Rect, time_range: TimeRange, tuning: &FrettedTuning) -> Self {
+        Tab {
+            rect,
+            time_range,
+            strings: tuning.strings.len() as u8,
+        }
+    }
+
+    pub fn beat_width(&self) -> f32 {
+        self.rect.width / (self.time_range.end - self.time_range.start).beats() as f32
+    }
+    pub fn string_spacing(&self) -> f32 { self.rect.height / self.strings.max(1) as f32 }
+    pub fn time_to_x(&self, time: Time) -> f32 {
+        self.rect.x + (time - self.time_range.start).beats() as f32 * self.beat_width()
+    }
+    pub fn string_to_y(&self, string: u8) -> f32 {
+        self.rect.bottom() - (string as f32 + 0.5) * self.string_spacing()
+    }
+
+    pub fn string_lines(&self) -> impl Iterator<Item = NoteLine> + '_ {
+        (0..self.strings).map(|string| NoteLine {
+            x_start: self.rect.left(),
+            x_end: self.rect.right(),
+            y: self.string_to_y(string),
+        })
+    }
+
+    /// Where to draw the fret number of `note`. Notes without a string or with a pitch
+    /// that can't be played on their string are skipped.
+    pub fn tab_note(&self, tuning: &FrettedTuning, note: &Note) -> Option<TabNote> {
+        let string = note.string?;
+        Some(TabNote {
+            x: self.time_to_x(note.time),
+            y: self.string_to_y(string),
+            string,
+            fret: tuning.fret_for(note)?,
+        })
+    }
+}
+
+pub struct Line {
+    pub from: Vec2,
+    pub to:   Vec2,
+}
+
+pub struct LyricSyllable {
+    /// Where the text is centered.
+    pub position: Vec2,
+    pub text:     String,
+    pub verse:    u8,
+}
+
+pub struct LyricsLayout {
+    pub syllables: Vec<LyricSyllable>,
+    /// Centers of the hyphens between syllables of a word.
+    pub hyphens:   Vec<Vec2>,
+    /// Melisma lines from a syllable to the end of the last note it is held over.
+    pub extenders: Vec<Line>,
+}
+
+/// Layout for the lyrics under a staff, one line per verse.
+#[derive(Debug, Clone, Copy)]
+pub struct Lyrics {
+    pub rect: Rect,
+    pub time_range: TimeRange,
+}
+impl Lyrics {
+    pub fn new(rect: Rect, time_range: TimeRange) -> Self { Lyrics { rect, time_range } }
+
+    pub fn beat_width(&self) -> f32 {
+        self.rect.width / (self.time_range.end - self.time_range.start).beats() as f32
+    }
+    pub fn time_to_x(&self, time: Time) -> f32 {
+        self.rect.x + (time - self.time_range.start).beats() as f32 * self.beat_width()
+    }
+
+    pub fn layout(&self, part: &Part) -> LyricsLayout {
+        let verses = part.verses();
+        let line_height = self.rect.height / verses.len().max(1) as f32;
+        let mut layout = LyricsLayout {
+            syllables: Vec::new(),
+            hyphens:   Vec::new(),
+            extenders: Vec::new(),
+        };
+
+        for (line, verse) in verses.into_iter().enumerate() {
+            let y = self.rect.top() + (line as f32 + 0.5) * line_height;
+            let lyrics = part.verse(verse);
+            for (i, (note, lyric)) in lyrics.iter().enumerate() {
+                let start = part.notes[*note].time;
+                let x = self.time_to_x(start);
+                layout.syllables.push(LyricSyllable {
+                    position: Vec2 { x, y },
+                    text: lyric.text.clone(),
+                    verse,
+                });
+
+                let next = lyrics.get(i + 1).map(|(next, _)| part.notes[*next].time);
+                if lyric.syllabic.continues() {
+                    if let Some(next) = next {
+                        layout.hyphens.push(Vec2 {
+                            x: (x + self.time_to_x(next)) * 0.5,
+                            y,
+                        });
+                    }
+                }
+                if lyric.extend {
+                    let end = part
+                        .notes
+                        .iter()
+                        .filter(|n| n.time >= start && next.is_none_or(|next| n.time < next))
+                        .map(|n| n.time + n.duration)
+                        .max()
+                        .unwrap_or(start);
+                    layout.extenders.push(Line {
+                        from: Vec2 { x, y },
+                        to:   Vec2 {
+                            x: self.time_to_x(end),
+                            y,
+                        },
+                    });
+                }
+            }
+        }
+        layout
+    }
+}
+
+pub struct DiagramDot {
+    pub center: Vec2,
+    pub radius: f32,
+    pub finger: Option<Finger>,
+}
+
+pub struct DiagramBarre {
+    pub from:   Vec2,
+    pub to:     Vec2,
+    pub finger: Finger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringMarker {
+    Open,
+    Muted,
+}
+
+/// Everything needed to draw a chord diagram.
+pub struct ChordDiagramLayout {
+    /// Horizontal lines, the first one is the nut if `base_fret` is 1.
+    pub fret_lines: Vec<Line>,
+    pub string_lines: Vec<Line>,
+    /// The fret of the first row, shown as e.g. "5fr" next to the grid if it isn't 1.
+    pub base_fret: u8,
+    pub dots: Vec<DiagramDot>,
+    pub barre: Option<DiagramBarre>,
+    /// X/O markers above the nut.
+    pub markers: Vec<(Vec2, StringMarker)>,
+}
+
+/// Layout for a chord diagram. String 0 is drawn on the left, the top row is reserved for
+/// the open and muted markers.
+#[derive(Debug, Clone, Copy)]
+pub struct ChordDiagram {
+    pub rect:  Rect,
+    pub frets: u8,
+}
+impl ChordDiagram {
+    pub fn new(rect: Rect) -> Self { ChordDiagram { rect, frets: 4 } }
+
+    pub fn layout(&self, tuning: &FrettedTuning, voicing: &Voicing) -> ChordDiagramLayout {
+        let strings = voicing.frets.len().max(1);
+        let string_spacing = self.rect.width / strings as f32;
+        let fret_spacing = self.rect.height / (self.frets as f32 + 1.0);
+        let left = self.rect.left() + string_spacing * 0.5;
+        let right = self.rect.right() - string_spacing * 0.5;
+        let top = self.rect.top() + fret_spacing;
+
+        let base_fret = match voicing.highest_fret(tuning) {
+            Some(highest) if highest > self.frets => voicing.lowest_fret(tuning).unwrap_or(1),
+            _ => 1,
+        };
+
+        let string_x = |string: u8| left + string as f32 * string_spacing;
+        // Dots sit between the fret lines
+        let fret_y = |fret: u8| top + ((fret - base_fret) as f32 + 0.5) * fret_spacing;
+
+        let fret_lines = (0..=self.frets)
+            .map(|i| {
+                let y = top + i as f32 * fret_spacing;
+                Line {
+                    from: Vec2 { x: left, y },
+                    to:   Vec2 { x: right, y },
+                }
+            })
+            .collect();
+        let string_lines = (0..strings as u8)
+            .map(|string| Line {
+                from: Vec2 {
+                    x: string_x(string),
+                    y: top,
+                },
+                to:   Vec2 {
+                    x: string_x(string),
+                    y: self.rect.bottom(),
+                },
+            })
+            .collect();
+
+        let markers = voicing
+            .frets
+            .iter()
+            .enumerate()
+            .filter_map(|(string, fret)| {
+                let string = string as u8;
+                let marker = match fret {
+                    None => StringMarker::Muted,
+                    Some(fret) if *fret == tuning.open_fret(string) => StringMarker::Open,
+                    Some(_) => return None,
+                };
+                let center = Vec2 {
+                    x: string_x(string),
+                    y: self.rect.top() + fret_spacing * 0.5,
+                };
+                Some((center, marker))
+            })
+            .collect();
+
+        let barre = voicing.barre.map(|barre| DiagramBarre {
+            from:   Vec2 {
+                x: string_x(barre.first_string),
+                y: fret_y(barre.fret),
+            },
+            to:     Vec2 {
+                x: string_x(barre.last_string),
+                y: fret_y(barre.fret),
+            },
+            finger: barre.finger,
+        });
+
+        let dots = voicing
+            .fretted(tuning)
+            .filter(|(string, fret)| {
+                voicing.barre.is_none_or(|barre| {
+                    barre.fret != *fret
+                        || !(barre.first_string..=barre.last_string).contains(string)
+                })
+            })
+            .filter(|(_, fret)| *fret >= base_fret && *fret < base_fret + self.frets)
+            .map(|(string, fret)| DiagramDot {
+                center: Vec2 {
+                    x: string_x(string),
+                    y: fret_y(fret),
+                },
+                radius: string_spacing.min(fret_spacing) * 0.35,
+                finger: voicing.fingers[string as usize],
+            })
+            .collect();
+
+        ChordDiagramLayout {
+            fret_lines,
+            string_lines,
+            base_fret,
+            dots,
+            barre,
+            markers,
+        }
+    }
+}
+
+/// The number written on a chord diagram for a fretting finger.
+pub fn fretting_finger_label(finger: Finger) -> char {
+    match finger {
+        Finger::Thumb => 'T',
+        Finger::Index => '1',
+        Finger::Middle => '2',
+        Finger::Ring => '3',
+        Finger::Pinky => '4',
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use super::form::{copy_segments, state_before, unfold_timeline, FormMarking, Segment};
+use super::{bar_length, Part, Score};
+use crate::note::rhythm::{Duration, Time, TimeRange};
+
+/// A named region of the score, from its marking to the next section or the end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name:  String,
+    pub range: TimeRange,
+}
+
+impl Score {
+    /// End of the bar the last note ends in, by the bars of the first part.
+    pub fn last_bar_end(&self) -> Time {
+        let end = self.end();
+        self.parts
+            .first()
+            .and_then(|part| part.bars_until(end).last())
+            .map_or(end, |(start, signature)| start + bar_length(signature))
+    }
+
+    pub fn sections(&self) -> Vec<Section> {
+        let starts = self
+            .form_markings()
+            .into_iter()
+            .filter_map(|(time, marking)| match marking {
+                FormMarking::Section(name) => Some((*time, name.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let end = self.last_bar_end();
+        (0..starts.len())
+            .map(|i| {
+                let (start, name) = starts[i].clone();
+                let next = starts.get(i + 1).map_or(end.max(start), |(next, _)| *next);
+                Section {
+                    name,
+                    range: (start..next).into(),
+                }
+            })
+            .collect()
+    }
+    pub fn section_at(&self, time: Time) -> Option<Section> {
+        self.sections()
+            .into_iter()
+            .find(|section| section.range.contains(&time))
+    }
+    /// The first section starting after `time`.
+    pub fn next_section(&self, time: Time) -> Option<Section> {
+        self.sections()
+            .into_iter()
+            .find(|section| section.range.start > time)
+    }
+    /// The last section starting before `time`.
+    pub fn previous_section(&self, time: Time) -> Option<Section> {
+        self.sections()
+            .into_iter()
+            .rfind(|section| section.range.start < time)
+    }
+    /// Indices of the bars of the first part starting in the section.
+    pub fn section_bars(&self, section: &Section) -> Range<usize> {
+        let Some(part) = self.parts.first()
+        else {
+            return 0..0;
+        };
+        let bars = part
+            .bars_until(section.range.end)
+            .map(|(start, _)| start)
+            .collect::<Vec<_>>();
+        let first = bars.partition_point(|start| *start < section.range.start);
+        first..bars.len()
+    }
+    pub fn add_section(&mut self, time: Time, name: impl Into<String>) {
+        self.form.push((time, FormMarking::Section(name.into())));
+    }
+
+    pub fn rehearsal_marks(&self) -> Vec<(Time, &str)> {
+        self.form_markings()
+            .into_iter()
+            .filter_map(|(time, marking)| match marking {
+                FormMarking::Rehearsal(label) => Some((*time, label.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+    /// The last rehearsal mark at or before `time`.
+    pub fn rehearsal_mark_at(&self, time: Time) -> Option<(Time, &str)> {
+        self.rehearsal_marks()
+            .into_iter()
+            .rfind(|(start, _)| *start <= time)
+    }
+    /// Adds a rehearsal mark lettered after the ones before it and reletters the ones after.
+    pub fn add_rehearsal_mark(&mut self, time: Time) {
+        self.form
+            .push((time, FormMarking::Rehearsal(String::new())));
+        self.reletter_rehearsal_marks();
+    }
+    /// Letters the rehearsal marks A to Z, then AA, AB and so on, in time order.
+    pub fn reletter_rehearsal_marks(&mut self) {
+        let mut marks = self
+            .form
+            .iter_mut()
+            .filter(|(_, marking)| matches!(marking, FormMarking::Rehearsal(_)))
+            .collect::<Vec<_>>();
+        marks.sort_by_key(|(time, _)| *time);
+        for (i, (_, marking)) in marks.into_iter().enumerate() {
+            *marking = FormMarking::Rehearsal(rehearsal_letter(i));
+        }
+    }
+
+    /// Opens a gap of `length` at `at`, moving everything starting there or later.
+    pub fn insert_time(&mut self, at: Time, length: Duration) {
+        let shift = |time: &mut Time| {
+            if *time >= at {
+                *time += length;
+            }
+        };
+        for part in &mut self.parts {
+            part.notes.iter_mut().for_each(|n| shift(&mut n.time));
+            part.rests.iter_mut().for_each(|r| shift(&mut r.time));
+            part.time_signature.iter_mut().for_each(|(t, _)| shift(t));
+            part.key_signature.iter_mut().for_each(|(t, _)| shift(t));
+            part.dynamics.iter_mut().for_each(|(t, _)| shift(t));
+        }
+        self.tempo_map.iter_mut().for_each(|(t, _)| shift(t));
+        self.text_events.iter_mut().for_each(|e| shift(&mut e.time));
+        self.form.iter_mut().for_each(|(t, _)| shift(t));
+    }
+
+    /// Inserts a copy of `range` at `at`. The copied notes get new ids, signatures, dynamics
+    /// and tempo are copied along and return to what they were at `at` after the copy.
+    pub fn insert_copy(&mut self, range: TimeRange, at: Time) {
+        let original = self.clone();
+        self.insert_from(&original, range, at);
+    }
+    /// Inserts `range` of `source` at `at`. Notes keep their ids unless this score has them.
+    fn insert_from(&mut self, source: &Score, range: TimeRange, at: Time) {
+        let length = range.end - range.start;
+        if length <= Duration::default() {
+            return;
+        }
+        let target = self.clone();
+        self.insert_time(at, length);
+        let segment = Segment {
+            source: range,
+            start:  at,
+            pass:   1,
+        };
+        let segments = std::slice::from_ref(&segment);
+
+        let parts = self.parts.iter_mut().zip(&source.parts).zip(&target.parts);
+        for ((part, source), target) in parts {
+            let mut used = part.notes.iter().map(|n| n.id).collect::<BTreeSet<_>>();
+            let copy = copy_segments(source, segments, &mut used, false);
+            part.notes.extend(copy.notes);
+            part.notes.sort_by_key(|n| n.time);
+            part.next_id = part.next_note_id().0;
+            part.spanners.extend(copy.spanners);
+            part.lyrics.extend(copy.lyrics);
+            part.rests.extend(copy.rests);
+            part.rests.sort();
+
+            copy_timeline(
+                &mut part.time_signature,
+                &source.time_signature,
+                &segment,
+                |t| state_before(&source.time_signature, t),
+                state_before(&target.time_signature, at),
+            );
+            copy_timeline(
+                &mut part.key_signature,
+                &source.key_signature,
+                &segment,
+                |t| state_before(&source.key_signature, t),
+                state_before(&target.key_signature, at),
+            );
+            let dynamic_at =
+                |part: &Part, t| (!part.dynamics.is_empty()).then(|| part.dynamic_at(t));
+            copy_timeline(
+                &mut part.dynamics,
+                &source.dynamics,
+                &segment,
+                |t| dynamic_at(source, t),
+                dynamic_at(target, at),
+            );
+        }
+        let tempo_at =
+            |score: &Score, t| (!score.tempo_map.is_empty()).then(|| score.tempo().tempo_at(t));
+        copy_timeline(
+            &mut self.tempo_map,
+            &source.tempo_map,
+            &segment,
+            |t| tempo_at(source, t),
+            tempo_at(&target, at),
+        );
+        self.text_events.extend(
+            source
+                .text_events
+                .iter()
+                .filter(|event| range.contains(&event.time))
+                .map(|event| {
+                    let mut event = event.clone();
+                    event.time = segment.played_time(event.time);
+                    event
+                }),
+        );
+        self.text_events.sort_by_key(|event| event.time);
+        let form = unfold_timeline(&source.form, segments, |_| None);
+        self.form.extend(form);
+        self.form.sort_by_key(|(time, _)| *time);
+    }
+
+    /// Removes a section with everything in it, moving the rest of the score earlier.
+    pub fn delete_section(&mut self, index: usize) -> Option<Section> {
+        let section = self.sections().into_iter().nth(index)?;
+        self.remove_range(section.range);
+        Some(section)
+    }
+    /// Inserts a copy of a section right after it.
+    pub fn duplicate_section(&mut self, index: usize) -> Option<Section> {
+        let section = self.sections().into_iter().nth(index)?;
+        self.insert_copy(section.range, section.range.end);
+        self.reletter_rehearsal_marks();
+        Some(section)
+    }
+    /// Moves a section to before the section now at `to`, or to the end if `to` is past the
+    /// last one. Its notes keep their ids.
+    pub fn move_section(&mut self, from: usize, to: usize) -> Option<Section> {
+        let sections = self.sections();
+        let section = sections.get(from)?.clone();
+        if to == from || to == from + 1 {
+            return Some(section);
+        }
+        let at = sections
+            .get(to)
+            .map_or(self.last_bar_end(), |target| target.range.start);
+        let original = self.clone();
+        self.remove_range(section.range);
+        let at = match at > section.range.start {
+            true => at - (section.range.end - section.range.start),
+            false => at,
+        };
+        self.insert_from(&original, section.range, at);
+        self.reletter_rehearsal_marks();
+        Some(section)
+    }
+}
+
+/// Adds the events of `source` in the segment to `events`, followed by `restored`, the state in
+/// effect at the insertion point, if the copy changed it.
+fn copy_timeline<T: Clone + PartialEq>(
+    events: &mut Vec<(Time, T)>,
+    source: &[(Time, T)],
+    segment: &Segment,
+    state_at: impl Fn(Time) -> Option<T>,
+    restored: Option<T>,
+) {
+    let copied = unfold_timeline(source, std::slice::from_ref(segment), &state_at);
+    let after = segment.end();
+    if !events.iter().any(|(time, _)| *time == after)
+        && copied.last().map(|(_, state)| state) != restored.as_ref()
+    {
+        if let Some(restored) = restored {
+            events.push((after, restored));
+        }
+    }
+    events.extend(copied);
+    events.sort_by_key(|(time, _)| *time);
+}
+
+/// A, B, ... Z, AA, AB, ...
+fn rehearsal_letter(index: usize) -> String {
+    let mut index = index + 1;
+    let mut letters = Vec::new();
+    while index > 0 {
+        index -= 1;
+        letters.push(b'A' + (index % 26) as u8);
+        index /= 26;
+    }
+    letters.iter().rev().map(|c| *c as char).collect()
+}
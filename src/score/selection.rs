@@ -0,0 +1,139 @@
+use std::collections::BTreeSet;
+
+use super::edit::{Cursor, Edit, EditState};
+use super::Score;
+use crate::note::harmony::Interval;
+use crate::note::rhythm::Time;
+use crate::note::{Note, NoteId};
+
+/// Selected notes as `(part, id)` pairs, which unlike indices stay valid through edits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub notes: BTreeSet<(usize, NoteId)>,
+}
+
+impl Selection {
+    /// Notes inside any of the cursors.
+    pub fn from_cursors(score: &Score, cursors: &[Cursor]) -> Self {
+        Selection::by_property(score, |part, note| {
+            cursors.iter().any(|cursor| cursor.contains(part, note))
+        })
+    }
+    /// Notes for which `select` returns true, e.g. all notes louder than a velocity.
+    pub fn by_property(score: &Score, mut select: impl FnMut(usize, &Note) -> bool) -> Self {
+        let notes = score
+            .parts
+            .iter()
+            .enumerate()
+            .flat_map(|(p, part)| part.notes.iter().map(move |note| (p, note)))
+            .filter(|(p, note)| select(*p, note))
+            .map(|(p, note)| (p, note.id))
+            .collect();
+        Selection { notes }
+    }
+
+    pub fn is_empty(&self) -> bool { self.notes.is_empty() }
+    pub fn union(&self, other: &Selection) -> Selection {
+        Selection {
+            notes: self.notes.union(&other.notes).copied().collect(),
+        }
+    }
+    /// The selected notes that still exist, in the order of the parts.
+    pub fn notes<'a>(&'a self, score: &'a Score) -> impl Iterator<Item = (usize, &'a Note)> + 'a {
+        score.parts.iter().enumerate().flat_map(move |(p, part)| {
+            part.notes
+                .iter()
+                .filter(move |note| self.notes.contains(&(p, note.id)))
+                .map(move |note| (p, note))
+        })
+    }
+
+    /// Deletes the selected notes in one edit.
+    pub fn delete(&self, score: &Score) -> Edit {
+        let parts = self
+            .notes
+            .iter()
+            .map(|(part, _)| *part)
+            .collect::<BTreeSet<_>>();
+        Edit::Batch(
+            parts
+                .into_iter()
+                .filter_map(|part| {
+                    let ids = self
+                        .notes
+                        .iter()
+                        .filter(|(p, _)| *p == part)
+                        .map(|(_, id)| *id)
+                        .collect::<Vec<_>>();
+                    Edit::delete_notes(score, part, &ids)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl EditState {
+    pub fn selection(&self, score: &Score) -> Selection {
+        Selection::from_cursors(score, &self.cursors)
+    }
+}
+
+/// Copied notes, relative to the start of the first note and the first part.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clipboard {
+    pub notes: Vec<(usize, Note)>,
+}
+
+impl Clipboard {
+    pub fn copy(score: &Score, selection: &Selection) -> Self {
+        let notes = selection.notes(score).collect::<Vec<_>>();
+        let Some(start) = notes.iter().map(|(_, note)| note.time).min()
+        else {
+            return Clipboard::default();
+        };
+        let first_part = notes.iter().map(|(part, _)| *part).min().unwrap_or(0);
+
+        let mut notes = notes
+            .into_iter()
+            .map(|(part, note)| {
+                let mut note = note.clone();
+                note.id = NoteId::UNASSIGNED;
+                note.time = Time::ZERO + (note.time - start);
+                (part - first_part, note)
+            })
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|(part, note)| (note.time, *part));
+        Clipboard { notes }
+    }
+    /// Copies the selection and returns the edit removing it.
+    pub fn cut(score: &Score, selection: &Selection) -> (Self, Edit) {
+        (Clipboard::copy(score, selection), selection.delete(score))
+    }
+
+    /// Inserts the notes at `time`, with the first copied part going to `part`. Notes for parts
+    /// that don't exist are dropped. The pasted notes get new ids.
+    pub fn paste(&self, score: &Score, part: usize, time: Time, transpose: Interval) -> Edit {
+        // Each note goes after the ones pasted before it and gets the next free id
+        let mut scratch = score.clone();
+        let mut edits = Vec::new();
+        for (offset, note) in &self.notes {
+            let mut note = note.clone();
+            note.id = NoteId::UNASSIGNED;
+            note.time = time + (note.time - Time::ZERO);
+            note.pitch += transpose;
+            if let Some(edit) = Edit::insert_note(&scratch, part + offset, note) {
+                edit.apply(&mut scratch);
+                edits.push(edit);
+            }
+        }
+        Edit::Batch(edits)
+    }
+
+    /// A RON version for the system clipboard, with every detail of the notes.
+    #[cfg(feature = "serde")]
+    pub fn to_text(&self) -> String { ron::to_string(self).unwrap_or_default() }
+    /// Parses text from `to_text`, `None` if it is something else.
+    #[cfg(feature = "serde")]
+    pub fn from_text(text: &str) -> Option<Self> { ron::from_str(text).ok() }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use super::Part;
+use crate::note::{Note, NoteId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpannerKind {
+    /// Joins two notes of the same pitch into one sound.
+    Tie,
+    Slur,
+    PhrasingSlur,
+    /// Hairpin opening to the right.
+    Crescendo,
+    /// Hairpin closing to the right.
+    Diminuendo,
+    /// Notes are written this many octaves lower (8va is 1, 15ma is 2, 8vb is -1) than they
+    /// sound.
+    Ottava(i8),
+    /// Sustain pedal held from the start note until the end of the end note.
+    Pedal,
+}
+
+impl SpannerKind {
+    /// The MusicXML element, e.g. `<slur type="start"/>`.
+    pub fn musicxml_element(&self) -> &'static str {
+        match self {
+            SpannerKind::Tie => "tied",
+            SpannerKind::Slur | SpannerKind::PhrasingSlur => "slur",
+            SpannerKind::Crescendo | SpannerKind::Diminuendo => "wedge",
+            SpannerKind::Ottava(_) => "octave-shift",
+            SpannerKind::Pedal => "pedal",
+        }
+    }
+    /// What LilyPond writes after the start and the end note.
+    pub fn lilypond(&self) -> (String, String) {
+        let (start, end) = match self {
+            SpannerKind::Tie => ("~", ""),
+            SpannerKind::Slur => ("(", ")"),
+            SpannerKind::PhrasingSlur => ("\\(", "\\)"),
+            SpannerKind::Crescendo => ("\\<", "\\!"),
+            SpannerKind::Diminuendo => ("\\>", "\\!"),
+            SpannerKind::Ottava(octaves) => {
+                return (format!("\\ottava #{octaves}"), "\\ottava #0".to_string());
+            }
+            SpannerKind::Pedal => ("\\sustainOn", "\\sustainOff"),
+        };
+        (start.to_string(), end.to_string())
+    }
+}
+
+/// A marking from one note to another. Notes are referenced by id, so spanners stay attached
+/// when notes are edited, moved or transposed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spanner {
+    pub kind:  SpannerKind,
+    pub start: NoteId,
+    pub end:   NoteId,
+}
+
+impl Spanner {
+    pub fn new(kind: SpannerKind, start: NoteId, end: NoteId) -> Self {
+        Spanner { kind, start, end }
+    }
+    /// Indices of the start and end note, looked up in `Part::note_indices`.
+    pub fn notes_in(&self, indices: &BTreeMap<NoteId, usize>) -> Option<(usize, usize)> {
+        Some((*indices.get(&self.start)?, *indices.get(&self.end)?))
+    }
+}
+
+impl Part {
+    /// Indices of the start and end note.
+    pub fn spanner_notes(&self, spanner: &Spanner) -> Option<(usize, usize)> {
+        Some((
+            self.note_index(spanner.start)?,
+            self.note_index(spanner.end)?,
+        ))
+    }
+    /// Removes spanners whose notes have been deleted.
+    pub fn remove_dangling_spanners(&mut self) {
+        let spanners = std::mem::take(&mut self.spanners);
+        self.spanners = spanners
+            .into_iter()
+            .filter(|spanner| self.spanner_notes(spanner).is_some())
+            .collect();
+    }
+
+    /// Octaves the note is written lower than it sounds because of ottava lines.
+    pub fn ottava_of(&self, note: usize) -> i8 {
+        let Some(note) = self.notes.get(note)
+        else {
+            return 0;
+        };
+        self.spanners
+            .iter()
+            .filter_map(|spanner| match spanner.kind {
+                SpannerKind::Ottava(octaves) => Some((octaves, self.spanner_notes(spanner)?)),
+                _ => None,
+            })
+            .filter(|(_, (start, end))| {
+                self.notes[*start].time <= note.time && note.time <= self.notes[*end].time
+            })
+            .map(|(octaves, _)| octaves)
+            .sum()
+    }
+
+    /// The notes as they sound, with their index in `notes`: tied notes are merged into the
+    /// first one, notes under a pedal sound until it is released and articulations are applied.
+    pub fn playback_notes(&self) -> Vec<(usize, Note)> {
+        let indices = self.note_indices();
+        // First note of each tie chain
+        let mut heads = (0..self.notes.len()).collect::<Vec<_>>();
+        let mut ties = self
+            .spanners
+            .iter()
+            .filter(|spanner| spanner.kind == SpannerKind::Tie)
+            .filter_map(|spanner| spanner.notes_in(&indices))
+            .filter(|(start, end)| self.notes[*start].time < self.notes[*end].time)
+            .collect::<Vec<_>>();
+        ties.sort_by_key(|(start, _)| self.notes[*start].time);
+        let mut ends = BTreeMap::new();
+        for (start, end) in ties {
+            let head = heads[start];
+            heads[end] = head;
+            let end_time = self.notes[end].time + self.notes[end].duration;
+            let entry = ends.entry(head).or_insert(end_time);
+            *entry = (*entry).max(end_time);
+        }
+
+        let pedals = self
+            .spanners
+            .iter()
+            .filter(|spanner| spanner.kind == SpannerKind::Pedal)
+            .filter_map(|spanner| spanner.notes_in(&indices))
+            .map(|(start, end)| {
+                let end = &self.notes[end];
+                (self.notes[start].time, end.time + end.duration)
+            })
+            .collect::<Vec<_>>();
+
+        self.notes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| heads[*i] == *i)
+            .map(|(i, note)| {
+                let mut note = note.clone();
+                let mut end = ends.get(&i).copied().unwrap_or(note.time + note.duration);
+                for (pedal_start, pedal_end) in &pedals {
+                    if *pedal_start <= note.time && note.time < *pedal_end {
+                        end = end.max(*pedal_end);
+                    }
+                }
+                note.duration = end - note.time;
+                (i, note)
+            })
+            .flat_map(|(i, note)| {
+                self.articulated_notes(&note)
+                    .into_iter()
+                    .map(move |note| (i, note))
+            })
+            .collect()
+    }
+}
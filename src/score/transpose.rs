@@ -0,0 +1,105 @@
+use super::edit::Edit;
+use super::selection::Selection;
+use super::{Part, Score};
+use crate::note::harmony::{Interval, Pitch};
+use crate::note::rhythm::Time;
+use crate::note::{Note, NoteId};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Transposition {
+    /// By a fixed interval, the key signatures move along.
+    Chromatic(Interval),
+    /// By notes of the key signature in effect, e.g. 2 turns C E G into E G B in C major.
+    Diatonic(i32),
+}
+
+impl Transposition {
+    pub fn pitch(&self, part: &Part, time: Time, pitch: Pitch) -> Pitch {
+        match self {
+            Transposition::Chromatic(interval) => pitch + *interval,
+            Transposition::Diatonic(steps) => part.key_signature_at(time).step_pitch(pitch, *steps),
+        }
+    }
+
+    /// A transposed copy of `part`. Pitches are spelled from the key signatures, which are
+    /// transposed as well. With `fold` notes outside the instrument's range are moved by
+    /// octaves until they fit.
+    pub fn part(&self, part: &Part, fold: bool) -> Part {
+        let mut transposed = part.clone();
+        for (note, original) in transposed.notes.iter_mut().zip(&part.notes) {
+            note.pitch = self.folded(part, original.time, original.pitch, fold);
+        }
+        if let Transposition::Chromatic(interval) = self {
+            for (_, key) in &mut transposed.key_signature {
+                *key = key.transposed(*interval);
+            }
+        }
+        transposed
+    }
+    pub fn score(&self, score: &Score, fold: bool) -> Score {
+        let mut score = score.clone();
+        for part in &mut score.parts {
+            *part = self.part(part, fold);
+        }
+        score
+    }
+
+    /// Transposes the selected notes as one undoable edit. Key signatures stay as they are.
+    pub fn selection(&self, score: &Score, selection: &Selection, fold: bool) -> Edit {
+        let notes = selection.notes.iter().copied().collect::<Vec<_>>();
+        Edit::Batch(set_pitches(score, &notes, |part, note| {
+            self.folded(&score.parts[part], note.time, note.pitch, fold)
+        }))
+    }
+
+    /// Undoable version of `part`, including the key signatures.
+    pub fn part_edit(&self, score: &Score, part: usize, fold: bool) -> Option<Edit> {
+        let transposed = self.part(score.parts.get(part)?, fold);
+        let notes = transposed
+            .notes
+            .iter()
+            .map(|note| (part, note.id))
+            .collect::<Vec<_>>();
+        let mut edits = set_pitches(score, &notes, |_, note| {
+            self.folded(&score.parts[part], note.time, note.pitch, fold)
+        });
+        edits.push(Edit::SetKeySignatures {
+            part,
+            old: score.parts[part].key_signature.clone(),
+            new: transposed.key_signature,
+        });
+        Some(Edit::Batch(edits))
+    }
+
+    fn folded(&self, part: &Part, time: Time, pitch: Pitch, fold: bool) -> Pitch {
+        let pitch = self.pitch(part, time, pitch);
+        match &part.instrument {
+            Some(instrument) if fold => instrument.fold_into_range(pitch),
+            _ => pitch,
+        }
+    }
+}
+
+/// Edits setting the pitch of the notes given as `(part, id)`, each made for the score the ones
+/// before it leave.
+fn set_pitches(
+    score: &Score,
+    notes: &[(usize, NoteId)],
+    pitch: impl Fn(usize, &Note) -> Pitch,
+) -> Vec<Edit> {
+    let mut scratch = score.clone();
+    let mut edits = Vec::new();
+    for (part, id) in notes {
+        let Some(note) = scratch.parts.get(*part).and_then(|p| p.note(*id))
+        else {
+            continue;
+        };
+        let pitch = pitch(*part, note);
+        if let Some(edit) = Edit::set_pitch(&scratch, *part, *id, pitch) {
+            edit.apply(&mut scratch);
+            edits.push(edit);
+        }
+    }
+    edits
+}
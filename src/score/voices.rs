@@ -0,0 +1,269 @@
+use super::{bar_length, Part};
+use crate::note::rhythm::{Duration, Time};
+use crate::note::Note;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rest {
+    pub time:     Time,
+    pub duration: Duration,
+    pub voice:    u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceIssue {
+    /// The voice leaves this much of the bar empty.
+    Incomplete {
+        bar:     usize,
+        voice:   u8,
+        missing: Duration,
+    },
+    /// The notes and rests of the voice are longer than the bar.
+    Overfull {
+        bar:    usize,
+        voice:  u8,
+        excess: Duration,
+    },
+    /// Something starts at `time` before the previous note or rest of the voice has ended.
+    Overlap {
+        bar:   usize,
+        voice: u8,
+        time:  Time,
+    },
+}
+
+/// Note values that can be written with a single symbol: plain, dotted and triplets, from a
+/// whole note down to a sixty-fourth.
+pub fn notatable_durations() -> Vec<Duration> {
+    let mut durations = Vec::new();
+    let mut plain = Duration::WHOLE;
+    for _ in 0..7 {
+        durations.extend([plain, plain + plain / 2, plain * 2 / 3]);
+        plain = plain / 2;
+    }
+    durations.sort_by(|a, b| b.cmp(a));
+    durations.dedup();
+    durations
+}
+
+/// Splits `duration` into notatable values, longest first. Whatever is too short for a
+/// sixty-fourth triplet is dropped.
+pub fn split_notatable(duration: Duration) -> Vec<Duration> {
+    let values = notatable_durations();
+    let mut remaining = duration;
+    let mut parts = Vec::new();
+    while let Some(value) = values.iter().find(|value| **value <= remaining) {
+        parts.push(*value);
+        remaining = remaining - *value;
+    }
+    parts
+}
+
+/// Infers a voice for every note, keeping the voices that are already set.
+///
+/// Notes starting together with the same duration form a chord in one voice. Otherwise each
+/// note goes to a free voice whose last pitch is closest, higher notes to lower voice numbers,
+/// opening new voices up to `max_voices`.
+pub fn separate_voices(notes: &[Note], max_voices: u8) -> Vec<u8> {
+    let max_voices = max_voices.max(1) as usize;
+    let mut order = (0..notes.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| {
+        let (a, b) = (&notes[*a], &notes[*b]);
+        a.time.cmp(&b.time).then(b.pitch.0.total_cmp(&a.pitch.0))
+    });
+
+    // End and last pitch of every voice
+    let mut voices: Vec<(Time, f32)> = Vec::new();
+    let mut assigned = vec![0u8; notes.len()];
+    // Start, duration, voice and lowest pitch of the last chord
+    let mut chord: Option<(Time, Duration, u8, f32)> = None;
+
+    for i in order {
+        let note = &notes[i];
+        let distance = |pitch: f32| (pitch - note.pitch.0).abs();
+        let closest_free = voices
+            .iter()
+            .enumerate()
+            .filter(|(_, (end, _))| *end <= note.time)
+            .min_by(|(_, (_, a)), (_, (_, b))| distance(*a).total_cmp(&distance(*b)))
+            .map(|(voice, (_, pitch))| (voice as u8, distance(*pitch)));
+
+        let voice = match (note.voice, chord, closest_free) {
+            (Some(voice), ..) => voice,
+            // Joins the chord unless a free voice continues more smoothly
+            (None, Some((time, duration, voice, lowest)), free)
+                if time == note.time
+                    && duration == note.duration
+                    && free.is_none_or(|(_, free)| free >= distance(lowest)) =>
+            {
+                voice
+            }
+            (None, _, Some((voice, _))) => voice,
+            (None, _, None) if voices.len() < max_voices => voices.len() as u8,
+            // Everything is busy, share the voice that is free first
+            (None, _, None) => voices
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (end, _))| *end)
+                .map_or(0, |(voice, _)| voice as u8),
+        };
+
+        if voices.len() <= voice as usize {
+            voices.resize(voice as usize + 1, (Time::ZERO, 0.0));
+        }
+        let end = note.time + note.duration;
+        let state = &mut voices[voice as usize];
+        *state = (state.0.max(end), note.pitch.0);
+        chord = Some((note.time, note.duration, voice, note.pitch.0));
+        assigned[i] = voice;
+    }
+    assigned
+}
+
+impl Part {
+    pub fn voice_of(&self, note: usize) -> u8 {
+        self.notes
+            .get(note)
+            .and_then(|note| note.voice)
+            .unwrap_or(0)
+    }
+    pub fn voices(&self) -> Vec<u8> {
+        let mut voices = self
+            .notes
+            .iter()
+            .map(|note| note.voice.unwrap_or(0))
+            .chain(self.rests.iter().map(|rest| rest.voice))
+            .collect::<Vec<_>>();
+        voices.sort();
+        voices.dedup();
+        voices
+    }
+
+    /// Sets the voice of every note that doesn't have one yet.
+    pub fn infer_voices(&mut self, max_voices: u8) {
+        let voices = separate_voices(&self.notes, max_voices);
+        for (note, voice) in self.notes.iter_mut().zip(voices) {
+            note.voice.get_or_insert(voice);
+        }
+    }
+
+    /// Replaces the rests with ones filling every gap of every voice, split at bar lines into
+    /// notatable values. The first voice is filled in every bar, the others only in bars where
+    /// they have notes.
+    pub fn fill_rests(&mut self) {
+        self.rests.clear();
+        let end = self
+            .notes
+            .iter()
+            .map(|n| n.time + n.duration)
+            .max()
+            .unwrap_or(Time::ZERO);
+        let bars = self.bars_until(end).collect::<Vec<_>>();
+
+        let mut rests = Vec::new();
+        for voice in self.voices() {
+            for (start, signature) in &bars {
+                let bar_end = *start + bar_length(*signature);
+                let mut notes = self
+                    .notes
+                    .iter()
+                    .filter(|n| n.voice.unwrap_or(0) == voice)
+                    .filter(|n| n.time < bar_end && n.time + n.duration > *start)
+                    .collect::<Vec<_>>();
+                if notes.is_empty() && voice != 0 {
+                    continue;
+                }
+                notes.sort_by_key(|n| n.time);
+
+                let mut cursor = *start;
+                let gaps = notes
+                    .iter()
+                    .map(|n| (n.time, n.time + n.duration))
+                    .chain([(bar_end, bar_end)]);
+                for (note_start, note_end) in gaps {
+                    let mut time = cursor;
+                    if note_start > cursor {
+                        for duration in split_notatable(note_start - cursor) {
+                            rests.push(Rest {
+                                time,
+                                duration,
+                                voice,
+                            });
+                            time += duration;
+                        }
+                    }
+                    cursor = cursor.max(note_end);
+                }
+            }
+        }
+        rests.sort();
+        self.rests = rests;
+    }
+
+    /// Checks that the notes and rests of every voice fill each bar exactly once. Chords count
+    /// once, notes crossing a bar line count for both bars.
+    pub fn check_voices(&self) -> Vec<VoiceIssue> {
+        let end = self
+            .notes
+            .iter()
+            .map(|n| n.time + n.duration)
+            .chain(self.rests.iter().map(|r| r.time + r.duration))
+            .max()
+            .unwrap_or(Time::ZERO);
+
+        let mut issues = Vec::new();
+        for voice in self.voices() {
+            let mut items = self
+                .notes
+                .iter()
+                .filter(|n| n.voice.unwrap_or(0) == voice)
+                .map(|n| (n.time, n.duration))
+                .chain(
+                    self.rests
+                        .iter()
+                        .filter(|r| r.voice == voice)
+                        .map(|r| (r.time, r.duration)),
+                )
+                .collect::<Vec<_>>();
+            items.sort();
+            items.dedup();
+
+            for (bar, (start, signature)) in self.bars_until(end).enumerate() {
+                let bar_end = start + bar_length(signature);
+                let mut cursor = start;
+                let mut filled = Duration::default();
+                for (time, duration) in &items {
+                    let (from, to) = ((*time).max(start), (*time + *duration).min(bar_end));
+                    if from >= to {
+                        continue;
+                    }
+                    if from < cursor {
+                        issues.push(VoiceIssue::Overlap {
+                            bar,
+                            voice,
+                            time: *time,
+                        });
+                    }
+                    filled = filled + (to - from);
+                    cursor = cursor.max(to);
+                }
+                let length = bar_end - start;
+                if filled < length {
+                    issues.push(VoiceIssue::Incomplete {
+                        bar,
+                        voice,
+                        missing: length - filled,
+                    });
+                }
+                else if filled > length {
+                    issues.push(VoiceIssue::Overfull {
+                        bar,
+                        voice,
+                        excess: filled - length,
+                    });
+                }
+            }
+        }
+        issues
+    }
+}
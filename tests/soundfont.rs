@@ -0,0 +1,115 @@
+//! `data/test.sf2` has a looped 441 Hz sine in bank 0, program 0 on all keys and a decaying
+//! noise drum kit in bank 128 on keys 35 to 81, both sampled at 22050 Hz.
+
+use music_notation::audio::{render, AudioBuffer, RenderOptions, Sampler, SoundFont};
+use music_notation::instrument::Instrument;
+use music_notation::note::articulation::Velocity;
+use music_notation::note::harmony::Pitch;
+use music_notation::note::rhythm::{Duration, Time};
+use music_notation::note::Note;
+use music_notation::score::{Part, Score};
+
+fn font() -> SoundFont {
+    SoundFont::load(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/test.sf2")).unwrap()
+}
+
+fn part(pitch: i32, instrument: Option<Instrument>) -> Part {
+    Part {
+        instrument,
+        notes: vec![Note {
+            time: Time::ZERO,
+            duration: Duration::QUARTER,
+            pitch: Pitch::from_midi(pitch),
+            velocity: Velocity(100),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+fn render_part(font: SoundFont, part: Part) -> AudioBuffer {
+    let score = Score {
+        parts: vec![part],
+        ..Default::default()
+    };
+    render(&score, &mut Sampler::new(font), &RenderOptions::default())
+}
+
+#[test]
+fn loads_presets() {
+    let font = font();
+    assert_eq!(font.name, "Test");
+    let sine = font.preset(0, 0).unwrap();
+    assert_eq!(sine.name, "Sine");
+    assert_eq!(sine.regions.len(), 1);
+    assert!(sine.regions[0].looping);
+    assert_eq!(sine.regions[0].root_key, 69);
+    let drums = font.preset(128, 0).unwrap();
+    assert_eq!(drums.regions[0].keys, 35..=81);
+}
+
+#[test]
+fn renders_looped_sample_at_pitch() {
+    // A quarter at 120 bpm is half a second, far longer than the 500 sample sine
+    let buffer = render_part(font(), part(69, None));
+    assert!(buffer.seconds() >= 0.5);
+    assert!(buffer.peak() > 0.1);
+
+    // Count rising zero crossings of the left channel while the note is held
+    let left = buffer
+        .samples
+        .iter()
+        .step_by(2)
+        .take(buffer.sample_rate as usize * 4 / 10)
+        .collect::<Vec<_>>();
+    let crossings = left
+        .windows(2)
+        .filter(|w| *w[0] < 0.0 && *w[1] >= 0.0)
+        .count();
+    let frequency = crossings as f64 / 0.4;
+    assert!((frequency - 441.0).abs() < 10.0, "{frequency} Hz");
+
+    // An octave up plays twice as fast
+    let buffer = render_part(font(), part(81, None));
+    let left = buffer
+        .samples
+        .iter()
+        .step_by(2)
+        .take(buffer.sample_rate as usize * 4 / 10)
+        .collect::<Vec<_>>();
+    let crossings = left
+        .windows(2)
+        .filter(|w| *w[0] < 0.0 && *w[1] >= 0.0)
+        .count();
+    assert!((crossings as f64 / 0.4 - 882.0).abs() < 20.0);
+}
+
+#[test]
+fn drums_use_the_drum_bank() {
+    let buffer = render_part(font(), part(36, Some(Instrument::drum_kit())));
+    assert!(buffer.peak() > 0.1);
+    // Outside the kit's keys
+    let buffer = render_part(font(), part(100, Some(Instrument::drum_kit())));
+    assert_eq!(buffer.peak(), 0.0);
+}
+
+#[test]
+fn drums_never_fall_back_to_melodic_presets() {
+    let mut font = font();
+    font.presets.retain(|preset| preset.bank != 128);
+    let buffer = render_part(font, part(36, Some(Instrument::drum_kit())));
+    assert_eq!(buffer.peak(), 0.0);
+}
+
+#[test]
+fn program_overrides_leave_drums_alone() {
+    // Drum parts have program 0 like the overridden sine, which unlike the kit has key 100
+    let mut sampler = Sampler::new(font());
+    sampler.programs.insert(0, (0, 0));
+    let score = Score {
+        parts: vec![part(100, Some(Instrument::drum_kit()))],
+        ..Default::default()
+    };
+    let buffer = render(&score, &mut sampler, &RenderOptions::default());
+    assert_eq!(buffer.peak(), 0.0);
+}